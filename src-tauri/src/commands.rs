@@ -1,8 +1,12 @@
 use crate::db::DbState;
-use crate::state_machine::{AppState, StateManager};
-use crate::utils::ai::{AiClient, AiRequest, ChatMessage};
+use crate::mcp::token_monitor::{
+    TokenMonitor, UsageBucket, UsageRecord, MODEL_SETTINGS, WINDOW_SETTING,
+};
+use crate::state_machine::{AppState, StateManager, Transition, Trigger};
+use crate::utils::ai::{AiClient, AiRequest, AiRequests, AiResponse, AiUsage, ChatMessage};
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
 
 // ============ Task Types ============
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,6 +20,22 @@ pub struct TaskData {
     pub tag: Option<String>,
     pub assignee: Option<String>,
     pub is_reworked: Option<bool>,
+    /// Set by the coder when the task is ready to hand over; gates entering Reviewer.
+    /// Cleared when the status changes or the review ends. `None` on update keeps it.
+    #[serde(default)]
+    pub ready_for_review: Option<bool>,
+}
+
+/// Statuses accepted by the Kanban board (`TaskStatus` on the frontend).
+pub const TASK_STATUSES: [&str; 3] = ["todo", "doing", "done"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentData {
+    pub id: i64,
+    pub task_id: String,
+    pub author: Option<String>,
+    pub content: String,
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,15 +59,202 @@ pub struct RoleData {
     pub role_type: String, // 'ai' or 'human'
     pub system_prompt: Option<String>,
     pub is_default: bool,
+    /// MCP tools this role may list and call; `None` uses the built-in policy.
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+    /// MCP resource URIs (a trailing `*` matches a prefix); `None` uses the built-in policy.
+    #[serde(default)]
+    pub allowed_resources: Option<Vec<String>>,
+    /// Workflow state (`coder`/`reviewer`/`architect`) a custom role plays when
+    /// selected; `None` means coder.
+    #[serde(default)]
+    pub base_role: Option<String>,
 }
 
+/// Runs an AI request to completion. `request_id` (generated when omitted) lets
+/// `cancel_ai_chat` abort it; it is also announced by `ai-chat-started` before the
+/// provider is called.
 #[tauri::command]
 pub async fn execute_ai_chat(
+    app: AppHandle,
     db_state: State<'_, DbState>,
     messages: Vec<ChatMessage>,
     override_provider: Option<String>,
     override_model: Option<String>,
-) -> Result<String, String> {
+    task_id: Option<String>,
+    request_id: Option<String>,
+) -> Result<AiChatResult, String> {
+    let request = ai_request(&db_state, messages, override_provider, override_model)?;
+    let request_id = request_id.unwrap_or_else(AiRequests::new_id);
+    run_ai_request(&app, &request_id, request, task_id, false).await
+}
+
+/// Sent once an AI request is registered and can be cancelled (`ai-chat-started`).
+#[derive(Debug, Clone, Serialize)]
+pub struct AiChatStarted {
+    pub request_id: String,
+    pub provider: String,
+    pub model: String,
+}
+
+/// One text fragment of a streamed AI answer (`ai-chat-chunk` event).
+#[derive(Debug, Clone, Serialize)]
+pub struct AiChatChunk {
+    pub request_id: String,
+    pub delta: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AiRequestStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiChatResult {
+    pub request_id: String,
+    /// `Completed`, or `Cancelled` with the answer received up to that point.
+    pub status: AiRequestStatus,
+    pub text: String,
+    pub usage: AiUsage,
+}
+
+/// Streams an AI answer as `ai-chat-chunk` events keyed by `request_id`, then returns
+/// the assembled text and its usage.
+#[tauri::command]
+pub async fn stream_ai_chat(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    request_id: String,
+    messages: Vec<ChatMessage>,
+    override_provider: Option<String>,
+    override_model: Option<String>,
+    task_id: Option<String>,
+) -> Result<AiChatResult, String> {
+    let request = ai_request(&db_state, messages, override_provider, override_model)?;
+    run_ai_request(&app, &request_id, request, task_id, true).await
+}
+
+/// Aborts a running `execute_ai_chat`/`stream_ai_chat`; the request returns what it
+/// received so far with a cancelled status.
+#[tauri::command]
+pub fn cancel_ai_chat(ai_requests: State<'_, AiRequests>, id: String) -> Result<(), String> {
+    ai_requests.cancel(&id)
+}
+
+/// Runs one AI request until it ends or is cancelled, recording the outcome in
+/// `ai_requests` and its usage in the token ledger. Cancelling drops the reqwest
+/// future, which closes the connection to the provider.
+async fn run_ai_request(
+    app: &AppHandle,
+    request_id: &str,
+    request: AiRequest,
+    task_id: Option<String>,
+    stream: bool,
+) -> Result<AiChatResult, String> {
+    let db_state = app.state::<DbState>();
+    let token_monitor = app.state::<Arc<TokenMonitor>>();
+    let ai_requests = app.state::<AiRequests>();
+    let record = ai_usage_record(
+        &app.state::<StateManager>(),
+        &token_monitor,
+        &request,
+        task_id,
+    );
+
+    let cancelled = ai_requests.begin(request_id)?;
+    if let Err(e) = db_state.begin_ai_request(request_id, &request.provider, &request.model) {
+        ai_requests.finish(request_id);
+        return Err(e);
+    }
+    let _ = app.emit(
+        "ai-chat-started",
+        AiChatStarted {
+            request_id: request_id.to_string(),
+            provider: request.provider.clone(),
+            model: request.model.clone(),
+        },
+    );
+
+    let client = AiClient::new();
+    let mut partial = String::new();
+    let outcome = {
+        let call = async {
+            if stream {
+                client
+                    .execute_stream(request, |delta| {
+                        partial.push_str(delta);
+                        let _ = app.emit(
+                            "ai-chat-chunk",
+                            AiChatChunk {
+                                request_id: request_id.to_string(),
+                                delta: delta.to_string(),
+                            },
+                        );
+                    })
+                    .await
+            } else {
+                client.execute(request).await
+            }
+        };
+        tokio::select! {
+            result = call => Some(result),
+            _ = cancelled => None,
+        }
+    };
+    ai_requests.finish(request_id);
+
+    let (status, response) = match outcome {
+        Some(Ok(response)) => (AiRequestStatus::Completed, response),
+        Some(Err(e)) => {
+            let _ = db_state.finish_ai_request(
+                request_id,
+                AiRequestStatus::Failed,
+                &partial,
+                Some(&e),
+                None,
+            );
+            return Err(e);
+        }
+        // The provider bills what it generated before the connection closed
+        None => (
+            AiRequestStatus::Cancelled,
+            AiResponse {
+                text: partial,
+                usage: None,
+            },
+        ),
+    };
+
+    let usage = charge_ai_chat(&db_state, &token_monitor, record, &response);
+    db_state.finish_ai_request(request_id, status, &response.text, None, Some(usage))?;
+    if status == AiRequestStatus::Cancelled {
+        let _ = db_state.log_activity(
+            "AI_CANCELLED",
+            &format!(
+                "{} cancelled after {} output tokens",
+                request_id, usage.output_tokens
+            ),
+        );
+    }
+    Ok(AiChatResult {
+        request_id: request_id.to_string(),
+        status,
+        text: response.text,
+        usage,
+    })
+}
+
+/// Resolves provider, model, key and endpoint from the overrides and `settings`.
+fn ai_request(
+    db_state: &State<'_, DbState>,
+    messages: Vec<ChatMessage>,
+    override_provider: Option<String>,
+    override_model: Option<String>,
+) -> Result<AiRequest, String> {
     let provider = override_provider.unwrap_or_else(|| {
         get_setting(db_state.clone(), "ai_provider".to_string())
             .unwrap_or(None)
@@ -70,130 +277,120 @@ pub async fn execute_ai_chat(
         return Err("API Key is missing".to_string());
     }
 
-    let request = AiRequest {
+    Ok(AiRequest {
         provider,
         api_key,
         model,
         messages,
         endpoint,
-    };
+    })
+}
 
-    let client = AiClient::new();
-    client.execute(request).await
+/// The ledger entry for an AI chat request, with the prompt counted locally in case
+/// the provider reports no usage.
+fn ai_usage_record(
+    state_manager: &StateManager,
+    token_monitor: &TokenMonitor,
+    request: &AiRequest,
+    task_id: Option<String>,
+) -> UsageRecord {
+    let role = state_manager.get_state();
+    UsageRecord {
+        day: crate::mcp::token_monitor::today(),
+        source: "ai_chat",
+        session_id: None,
+        role: role.role_name().map(|_| role),
+        task_id,
+        provider: request.provider.clone(),
+        model: request.model.clone(),
+        requests: 1,
+        input_tokens: request
+            .messages
+            .iter()
+            .map(|m| token_monitor.count_tokens(&m.content))
+            .sum(),
+        output_tokens: 0,
+        cost_usd: None,
+    }
+}
+
+/// Persists the exchange, preferring the usage the provider reported.
+fn charge_ai_chat(
+    db_state: &DbState,
+    token_monitor: &TokenMonitor,
+    mut record: UsageRecord,
+    response: &AiResponse,
+) -> AiUsage {
+    let usage = response.usage.unwrap_or_else(|| AiUsage {
+        input_tokens: record.input_tokens,
+        output_tokens: token_monitor.count_tokens(&response.text),
+    });
+    record.input_tokens = usage.input_tokens;
+    record.output_tokens = usage.output_tokens;
+    token_monitor.charge(db_state, record);
+    usage
 }
 
 // ============ Task Commands ============
 #[tauri::command]
 pub fn get_tasks(db_state: State<'_, DbState>) -> Result<Vec<TaskData>, String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare(
-        "SELECT id, title, description, status, phase, priority, tag, assignee, is_reworked FROM tasks ORDER BY phase, priority"
-    ).map_err(|e| e.to_string())?;
-
-    let task_iter = stmt
-        .query_map([], |row| {
-            Ok(TaskData {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                description: row.get(2)?,
-                status: row.get(3)?,
-                phase: row.get(4)?,
-                priority: row.get(5)?,
-                tag: row.get(6)?,
-                assignee: row.get(7)?,
-                is_reworked: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
-            })
-        })
-        .map_err(|e| e.to_string())?;
+    db_state.get_tasks()
+}
 
-    let tasks: Result<Vec<_>, _> = task_iter.collect();
-    tasks.map_err(|e| e.to_string())
+#[tauri::command]
+pub fn get_task(db_state: State<'_, DbState>, id: String) -> Result<Option<TaskData>, String> {
+    db_state.get_task(&id)
 }
 
 #[tauri::command]
-pub fn create_task(db_state: State<'_, DbState>, task: TaskData) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO tasks (id, title, description, status, phase, priority, tag, assignee, is_reworked) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        rusqlite::params![
-            task.id,
-            task.title,
-            task.description,
-            task.status,
-            task.phase.unwrap_or_else(|| "PHASE 1".to_string()),
-            task.priority.unwrap_or_else(|| "3".to_string()),
-            task.tag,
-            task.assignee,
-            task.is_reworked.map(|v| if v { 1 } else { 0 }).unwrap_or(0),
-        ],
-    ).map_err(|e| e.to_string())?;
+pub fn create_task(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    task: TaskData,
+) -> Result<(), String> {
+    let id = task.id.clone();
+    db_state.create_task(task)?;
+    notify_task_changed(&app, &id);
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_task(db_state: State<'_, DbState>, task: TaskData) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE tasks SET 
-            title = ?1, 
-            description = ?2, 
-            status = ?3, 
-            phase = ?4, 
-            priority = ?5, 
-            tag = ?6, 
-            assignee = ?7, 
-            is_reworked = ?8,
-            updated_at = CURRENT_TIMESTAMP 
-         WHERE id = ?9",
-        rusqlite::params![
-            task.title,
-            task.description,
-            task.status,
-            task.phase,
-            task.priority,
-            task.tag,
-            task.assignee,
-            task.is_reworked.map(|v| if v { 1 } else { 0 }),
-            task.id,
-        ],
-    )
-    .map_err(|e| e.to_string())?;
+pub fn update_task(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    task: TaskData,
+) -> Result<(), String> {
+    let id = task.id.clone();
+    db_state.update_task(task)?;
+    notify_task_changed(&app, &id);
     Ok(())
 }
 
+/// Lets MCP clients subscribed to the board hear about edits made in the UI.
+fn notify_task_changed(app: &AppHandle, task_id: &str) {
+    if let Some(server) = app.try_state::<crate::mcp::sse::ServerState>() {
+        server.ctx.notify_task_changed(task_id);
+    }
+}
+
 #[tauri::command]
-pub fn delete_all_tasks(db_state: State<'_, DbState>) -> Result<(), String> {
+pub fn delete_all_tasks(app: AppHandle, db_state: State<'_, DbState>) -> Result<(), String> {
     let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
     conn.execute("DELETE FROM tasks", [])
         .map_err(|e| e.to_string())?;
+    if let Some(server) = app.try_state::<crate::mcp::sse::ServerState>() {
+        server
+            .ctx
+            .sessions
+            .notify_resource_updated(crate::mcp::resources::BOARD_URI);
+    }
     Ok(())
 }
 
 // ============ Spec Commands ============
 #[tauri::command]
 pub fn get_project_spec(db_state: State<'_, DbState>) -> Result<Option<SpecData>, String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, name, overview, tech_stack, data_structure, features, design, rules FROM project_spec WHERE id = 'default'")
-        .map_err(|e| e.to_string())?;
-
-    let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
-
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        Ok(Some(SpecData {
-            id: row.get(0).map_err(|e| e.to_string())?,
-            name: row.get(1).map_err(|e| e.to_string())?,
-            overview: row.get(2).map_err(|e| e.to_string())?,
-            tech_stack: row.get(3).map_err(|e| e.to_string())?,
-            data_structure: row.get(4).map_err(|e| e.to_string())?,
-            features: row.get(5).map_err(|e| e.to_string())?,
-            design: row.get(6).map_err(|e| e.to_string())?,
-            rules: row.get(7).map_err(|e| e.to_string())?,
-        }))
-    } else {
-        Ok(None)
-    }
+    db_state.get_project_spec()
 }
 
 #[tauri::command]
@@ -216,59 +413,54 @@ pub fn update_project_spec(db_state: State<'_, DbState>, spec: SpecData) -> Resu
 }
 
 #[tauri::command]
-pub fn delete_task(db_state: State<'_, DbState>, id: String) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM tasks WHERE id = ?1", [&id])
-        .map_err(|e| e.to_string())?;
+pub fn delete_task(app: AppHandle, db_state: State<'_, DbState>, id: String) -> Result<(), String> {
+    db_state.delete_task(&id)?;
+    notify_task_changed(&app, &id);
     Ok(())
 }
 
 #[tauri::command]
 pub fn update_task_status(
+    app: AppHandle,
     db_state: State<'_, DbState>,
     id: String,
     status: String,
 ) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE tasks SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
-        [&status, &id],
-    )
-    .map_err(|e| e.to_string())?;
+    db_state.update_task_status(&id, &status)?;
+    notify_task_changed(&app, &id);
     Ok(())
 }
 
-// ============ Role Commands ============
 #[tauri::command]
-pub fn get_roles(db_state: State<'_, DbState>) -> Result<Vec<RoleData>, String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare(
-        "SELECT id, name, agent_name, role_type, system_prompt, is_default FROM roles ORDER BY is_default DESC, created_at"
-    ).map_err(|e| e.to_string())?;
+pub fn add_task_comment(
+    db_state: State<'_, DbState>,
+    task_id: String,
+    author: Option<String>,
+    content: String,
+) -> Result<(), String> {
+    db_state.add_task_comment(&task_id, author.as_deref(), &content)
+}
 
-    let role_iter = stmt
-        .query_map([], |row| {
-            Ok(RoleData {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                agent_name: row.get(2)?,
-                role_type: row.get(3)?,
-                system_prompt: row.get(4)?,
-                is_default: row.get::<_, i32>(5)? != 0,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+pub fn get_task_comments(
+    db_state: State<'_, DbState>,
+    task_id: String,
+) -> Result<Vec<CommentData>, String> {
+    db_state.get_task_comments(&task_id)
+}
 
-    let roles: Result<Vec<_>, _> = role_iter.collect();
-    roles.map_err(|e| e.to_string())
+// ============ Role Commands ============
+#[tauri::command]
+pub fn get_roles(db_state: State<'_, DbState>) -> Result<Vec<RoleData>, String> {
+    db_state.get_roles()
 }
 
 #[tauri::command]
 pub fn create_role(db_state: State<'_, DbState>, role: RoleData) -> Result<(), String> {
     let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
     conn.execute(
-        "INSERT INTO roles (id, name, agent_name, role_type, system_prompt, is_default) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO roles (id, name, agent_name, role_type, system_prompt, is_default, allowed_tools, allowed_resources, base_role) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
         rusqlite::params![
             role.id,
             role.name,
@@ -276,6 +468,9 @@ pub fn create_role(db_state: State<'_, DbState>, role: RoleData) -> Result<(), S
             role.role_type,
             role.system_prompt,
             if role.is_default { 1 } else { 0 },
+            role.allowed_tools.map(|l| serde_json::json!(l).to_string()),
+            role.allowed_resources.map(|l| serde_json::json!(l).to_string()),
+            role.base_role,
         ],
     )
     .map_err(|e| e.to_string())?;
@@ -283,18 +478,68 @@ pub fn create_role(db_state: State<'_, DbState>, role: RoleData) -> Result<(), S
 }
 
 #[tauri::command]
-pub fn update_role(db_state: State<'_, DbState>, role: RoleData) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE roles SET name = ?1, agent_name = ?2, role_type = ?3, system_prompt = ?4 WHERE id = ?5 AND is_default = 0",
-        rusqlite::params![
-            role.name,
-            role.agent_name,
-            role.role_type,
-            role.system_prompt,
-            role.id,
-        ],
-    ).map_err(|e| e.to_string())?;
+pub fn update_role(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    role: RoleData,
+) -> Result<(), String> {
+    {
+        let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE roles SET name = ?1, agent_name = ?2, role_type = ?3, system_prompt = ?4,
+             allowed_tools = ?5, allowed_resources = ?6, base_role = ?7
+             WHERE id = ?8 AND is_default = 0",
+            rusqlite::params![
+                role.name,
+                role.agent_name,
+                role.role_type,
+                role.system_prompt,
+                role.allowed_tools.map(|l| serde_json::json!(l).to_string()),
+                role.allowed_resources
+                    .map(|l| serde_json::json!(l).to_string()),
+                role.base_role,
+                role.id,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    // The role may be the active one
+    if let Some(server) = app.try_state::<crate::mcp::sse::ServerState>() {
+        server
+            .ctx
+            .sessions
+            .notify_all("notifications/tools/list_changed");
+        server
+            .ctx
+            .sessions
+            .notify_all("notifications/resources/list_changed");
+    }
+    Ok(())
+}
+
+/// Unlike `update_role`, also applies to the built-in roles.
+#[tauri::command]
+pub fn set_role_permissions(
+    app: AppHandle,
+    db_state: State<'_, DbState>,
+    id: String,
+    allowed_tools: Option<Vec<String>>,
+    allowed_resources: Option<Vec<String>>,
+) -> Result<(), String> {
+    db_state.set_role_permissions(&id, allowed_tools.as_deref(), allowed_resources.as_deref())?;
+
+    // The active role's tool list may have changed under connected clients
+    if let Some(server) = app.try_state::<crate::mcp::sse::ServerState>() {
+        server
+            .ctx
+            .sessions
+            .notify_all("notifications/tools/list_changed");
+        server
+            .ctx
+            .sessions
+            .notify_all("notifications/resources/list_changed");
+    }
     Ok(())
 }
 
@@ -310,33 +555,108 @@ pub fn delete_role(db_state: State<'_, DbState>, id: String) -> Result<(), Strin
 // ============ State Machine Commands ============
 #[tauri::command]
 pub fn set_role(
+    db_state: State<'_, DbState>,
     state_manager: State<'_, StateManager>,
-    sse_state: State<'_, crate::mcp::sse::ServerState>,
     role: String,
 ) -> Result<(), String> {
-    let new_state = match role.as_str() {
-        "coder" => AppState::Coder,
-        "reviewer" => AppState::Reviewer,
-        "architect" => AppState::Architect,
-        _ => return Err("Invalid role".to_string()),
-    };
-    state_manager.set_state(new_state);
-
-    // Broadcast to MCP Clients
-    let _ = sse_state.tx.send(
-        serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": "notifications/identityChange",
-            "params": {
-                "role": role
-            }
-        })
-        .to_string(),
-    );
+    // `role` is a built-in role name or the id of a custom role row
+    let (new_state, role_id) = AppState::resolve_role(&db_state, &role)?;
+    // MCP clients are notified by the transition hook
+    state_manager.transition_as(&db_state, new_state, role_id, Trigger::User, None)?;
+    Ok(())
+}
 
+#[tauri::command]
+pub fn get_state_history(
+    db_state: State<'_, DbState>,
+    limit: Option<u32>,
+) -> Result<Vec<Transition>, String> {
+    db_state.get_transitions(limit.unwrap_or(50))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenUsageData {
+    pub model: String,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
+/// MCP tokens counted since startup, across all sessions.
+#[tauri::command]
+pub fn get_token_usage(token_monitor: State<'_, Arc<TokenMonitor>>) -> TokenUsageData {
+    let (input_tokens, output_tokens) = token_monitor.get_usage();
+    TokenUsageData {
+        model: token_monitor.model(),
+        input_tokens,
+        output_tokens,
+    }
+}
+
+/// Persisted token usage and estimated cost for the last `days` days (30 daily, 84
+/// weekly by default), per `period` (`day`/`week`) and `group_by` dimension.
+#[tauri::command]
+pub fn get_usage_report(
+    db_state: State<'_, DbState>,
+    token_monitor: State<'_, Arc<TokenMonitor>>,
+    period: String,
+    group_by: String,
+    days: Option<u32>,
+) -> Result<Vec<UsageBucket>, String> {
+    // Include the MCP usage still buffered in memory
+    token_monitor.flush(&db_state);
+    let days = days.unwrap_or(if period == "week" { 84 } else { 30 });
+    let since = chrono::Local::now().date_naive() - chrono::Days::new(days.into());
+    db_state.get_usage_breakdown(&period, &group_by, &since.format("%Y-%m-%d").to_string())
+}
+
+/// Resets an MCP session's context, lifting a token budget block.
+#[tauri::command]
+pub fn reset_mcp_session(
+    db_state: State<'_, DbState>,
+    sse_state: State<'_, crate::mcp::sse::ServerState>,
+    id: String,
+) -> Result<(), String> {
+    let session = sse_state
+        .ctx
+        .sessions
+        .get(&id)
+        .ok_or_else(|| format!("MCP session {} not found", id))?;
+    crate::mcp::reset::reset_session(&db_state, &session);
     Ok(())
 }
 
+#[tauri::command]
+pub fn get_mcp_sessions(
+    sse_state: State<'_, crate::mcp::sse::ServerState>,
+) -> Vec<crate::mcp::session::SessionInfo> {
+    sse_state.ctx.sessions.list()
+}
+
+// ============ Airlock Commands ============
+#[tauri::command]
+pub fn get_airlock_requests(
+    sse_state: State<'_, crate::mcp::sse::ServerState>,
+) -> Result<Vec<crate::mcp::airlock::ApprovalRequest>, String> {
+    sse_state.ctx.airlock.list(&sse_state.ctx)
+}
+
+#[tauri::command]
+pub fn resolve_airlock_request(
+    sse_state: State<'_, crate::mcp::sse::ServerState>,
+    id: String,
+    approved: bool,
+    reason: Option<String>,
+) -> Result<(), String> {
+    use crate::mcp::airlock::Decision;
+
+    let decision = if approved {
+        Decision::Approved
+    } else {
+        Decision::Rejected(reason)
+    };
+    sse_state.ctx.airlock.resolve(&sse_state.ctx, &id, decision)
+}
+
 #[tauri::command]
 pub async fn save_md_file(content: String, filename: String) -> Result<(), String> {
     use std::io::Write;
@@ -360,27 +680,28 @@ pub async fn save_md_file(content: String, filename: String) -> Result<(), Strin
 
 #[tauri::command]
 pub fn get_setting(db_state: State<'_, DbState>, key: String) -> Result<Option<String>, String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT value FROM settings WHERE key = ?1")
-        .map_err(|e| e.to_string())?;
-    let mut rows = stmt.query([&key]).map_err(|e| e.to_string())?;
-
-    if let Some(row) = rows.next().map_err(|e| e.to_string())? {
-        Ok(Some(row.get(0).map_err(|e| e.to_string())?))
-    } else {
-        Ok(None)
-    }
+    db_state.get_setting(&key)
 }
 
 #[tauri::command]
-pub fn set_setting(db_state: State<'_, DbState>, key: String, value: String) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
-        [&key, &value],
-    )
-    .map_err(|e| e.to_string())?;
+pub fn set_setting(
+    db_state: State<'_, DbState>,
+    token_monitor: State<'_, Arc<TokenMonitor>>,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    {
+        let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            [&key, &value],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    if MODEL_SETTINGS.contains(&key.as_str()) || key == WINDOW_SETTING {
+        token_monitor.reload(&db_state);
+    }
     Ok(())
 }
 
@@ -453,36 +774,12 @@ pub fn log_activity(
     event_type: String,
     message: String,
 ) -> Result<(), String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO system_activity (event_type, message) VALUES (?1, ?2)",
-        [event_type, message],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+    db_state.log_activity(&event_type, &message)
 }
 
 #[tauri::command]
 pub fn get_activity(db_state: tauri::State<'_, DbState>) -> Result<Vec<ActivityData>, String> {
-    let conn = db_state.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, event_type, message, user_id, timestamp FROM system_activity ORDER BY timestamp DESC LIMIT 100")
-        .map_err(|e| e.to_string())?;
-
-    let activity_iter = stmt
-        .query_map([], |row| {
-            Ok(ActivityData {
-                id: row.get(0)?,
-                event_type: row.get(1)?,
-                message: row.get(2)?,
-                user_id: row.get(3)?,
-                timestamp: row.get(4)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let activities: Result<Vec<_>, _> = activity_iter.collect();
-    activities.map_err(|e| e.to_string())
+    db_state.get_activity()
 }
 
 #[tauri::command]
@@ -1,9 +1,22 @@
-use rusqlite::{Connection, Result};
-use std::sync::Mutex;
+use crate::commands::{
+    ActivityData, AiRequestStatus, CommentData, RoleData, SpecData, TaskData, TASK_STATUSES,
+};
+use crate::mcp::airlock::{ApprovalRequest, Decision};
+use crate::mcp::token_monitor::{UsageBucket, UsageRecord};
+use crate::state_machine::{AppState, StoredState, Transition};
+use crate::utils::ai::AiUsage;
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tauri::Manager;
 
+/// Bundle identifier from `tauri.conf.json`; Tauri stores app data under it.
+const APP_IDENTIFIER: &str = "com.antigravity.taskrails";
+
+/// Cheap to clone: all clones share the same connection.
+#[derive(Clone)]
 pub struct DbState {
-    pub conn: Mutex<Connection>,
+    pub conn: Arc<Mutex<Connection>>,
 }
 
 pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
@@ -11,11 +24,40 @@ pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
         .path()
         .app_data_dir()
         .expect("failed to get app data dir");
-    std::fs::create_dir_all(&app_dir).expect("failed to create app data dir");
+    open(&app_dir)
+}
+
+/// Adds a column to a table created by an earlier version of the app.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let exists = conn
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// The directory Tauri's `app_data_dir()` resolves to, computed without a running app.
+pub fn app_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+/// Opens (and migrates) `taskrails.db` inside `app_dir`.
+pub fn open(app_dir: &Path) -> Result<DbState> {
+    std::fs::create_dir_all(app_dir).expect("failed to create app data dir");
     let db_path = app_dir.join("taskrails.db");
 
     let conn = Connection::open(db_path)?;
 
+    // The desktop app and an IDE-spawned stdio server may share this file
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+
     // Enable foreign keys
     conn.execute("PRAGMA foreign_keys = ON;", [])?;
 
@@ -45,6 +87,7 @@ pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
         )",
         [],
     )?;
+    add_column_if_missing(&conn, "tasks", "ready_for_review", "INTEGER DEFAULT 0")?;
 
     // Roles table for custom AI agents and collaborators
     conn.execute(
@@ -60,6 +103,24 @@ pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
         [],
     )?;
 
+    // MCP allowlists per role (JSON arrays); NULL falls back to the built-in policy
+    add_column_if_missing(&conn, "roles", "allowed_tools", "TEXT")?;
+    add_column_if_missing(&conn, "roles", "allowed_resources", "TEXT")?;
+    // Workflow position (coder/reviewer/architect) a custom role takes when selected
+    add_column_if_missing(&conn, "roles", "base_role", "TEXT")?;
+
+    // Task comments left by agents (MCP) and collaborators
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS task_comments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            task_id TEXT NOT NULL,
+            author TEXT,
+            content TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
     // Logs table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS logs (
@@ -83,6 +144,104 @@ pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
         [],
     )?;
 
+    // Workflow state history, one row per role switch
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS state_transitions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            from_state TEXT NOT NULL,
+            to_state TEXT NOT NULL,
+            trigger TEXT NOT NULL,
+            reason TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(&conn, "state_transitions", "role_id", "TEXT")?;
+
+    // The active role, shared by the desktop app and the stdio server
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            state TEXT NOT NULL,
+            resume_state TEXT,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO app_state (id, state, updated_at) VALUES (1, 'Idle', datetime('now'))",
+        [],
+    )?;
+    // Custom role row playing the active state; NULL means the state's default row
+    add_column_if_missing(&conn, "app_state", "role_id", "TEXT")?;
+    // Process (`instances.id`) that entered the Airlock and owns its approval queue
+    add_column_if_missing(&conn, "app_state", "airlock_owner", "TEXT")?;
+
+    // Running TaskRails processes, so a hold whose owner died can be told apart
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS instances (
+            id TEXT PRIMARY KEY,
+            pid INTEGER NOT NULL,
+            heartbeat_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Agent writes held by the Airlock; `owner` is the process waiting for the decision
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS airlock_requests (
+            id TEXT PRIMARY KEY,
+            owner TEXT NOT NULL,
+            tool TEXT NOT NULL,
+            arguments TEXT NOT NULL,
+            role TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            client TEXT,
+            requested_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            decision TEXT,
+            reason TEXT
+        )",
+        [],
+    )?;
+
+    // Token usage and estimated cost, one row per day and accounting key
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS token_usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            day TEXT NOT NULL,
+            source TEXT NOT NULL,
+            session_id TEXT NOT NULL DEFAULT '',
+            role TEXT NOT NULL DEFAULT '',
+            task_id TEXT NOT NULL DEFAULT '',
+            provider TEXT NOT NULL DEFAULT '',
+            model TEXT NOT NULL DEFAULT '',
+            requests INTEGER NOT NULL DEFAULT 0,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            cost_usd REAL,
+            UNIQUE (day, source, session_id, role, task_id, provider, model)
+        )",
+        [],
+    )?;
+
+    // Built-in AI chat requests and how they ended, including cancelled partial output
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ai_requests (
+            id TEXT PRIMARY KEY,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            status TEXT NOT NULL,
+            output TEXT,
+            error TEXT,
+            input_tokens INTEGER,
+            output_tokens INTEGER,
+            started_at TEXT NOT NULL,
+            finished_at TEXT
+        )",
+        [],
+    )?;
+
     // Project Specification table for automated planning
     conn.execute(
         "CREATE TABLE IF NOT EXISTS project_spec (
@@ -146,6 +305,766 @@ pub fn init(app_handle: &tauri::AppHandle) -> Result<DbState> {
     */
 
     Ok(DbState {
-        conn: Mutex::new(conn),
+        conn: Arc::new(Mutex::new(conn)),
     })
 }
+
+// ============ Queries shared by Tauri commands and the MCP server ============
+impl DbState {
+    pub fn get_tasks(&self) -> Result<Vec<TaskData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn.prepare(
+            "SELECT id, title, description, status, phase, priority, tag, assignee, is_reworked, ready_for_review FROM tasks ORDER BY phase, priority"
+        ).map_err(|e| e.to_string())?;
+
+        let task_iter = stmt
+            .query_map([], task_from_row)
+            .map_err(|e| e.to_string())?;
+
+        let tasks: Result<Vec<_>, _> = task_iter.collect();
+        tasks.map_err(|e| e.to_string())
+    }
+
+    pub fn get_task(&self, id: &str) -> Result<Option<TaskData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT id, title, description, status, phase, priority, tag, assignee, is_reworked, ready_for_review FROM tasks WHERE id = ?1")
+            .map_err(|e| e.to_string())?;
+
+        let mut rows = stmt.query([id]).map_err(|e| e.to_string())?;
+
+        match rows.next().map_err(|e| e.to_string())? {
+            Some(row) => Ok(Some(task_from_row(row).map_err(|e| e.to_string())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn create_task(&self, task: TaskData) -> Result<(), String> {
+        if !TASK_STATUSES.contains(&task.status.as_str()) {
+            return Err(format!("Invalid status: {}", task.status));
+        }
+
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO tasks (id, title, description, status, phase, priority, tag, assignee, is_reworked, ready_for_review) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                task.id,
+                task.title,
+                task.description,
+                task.status,
+                task.phase.unwrap_or_else(|| "PHASE 1".to_string()),
+                task.priority.unwrap_or_else(|| "3".to_string()),
+                task.tag,
+                task.assignee,
+                task.is_reworked.map(|v| if v { 1 } else { 0 }).unwrap_or(0),
+                task.ready_for_review.map(|v| if v { 1 } else { 0 }).unwrap_or(0),
+            ],
+        ).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn update_task(&self, task: TaskData) -> Result<(), String> {
+        if !TASK_STATUSES.contains(&task.status.as_str()) {
+            return Err(format!("Invalid status: {}", task.status));
+        }
+
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE tasks SET 
+                title = ?1, 
+                description = ?2, 
+                status = ?3, 
+                phase = ?4, 
+                priority = ?5, 
+                tag = ?6, 
+                assignee = ?7, 
+                is_reworked = ?8,
+                ready_for_review = COALESCE(?9, CASE WHEN status = ?3 THEN ready_for_review ELSE 0 END),
+                updated_at = CURRENT_TIMESTAMP 
+             WHERE id = ?10",
+            rusqlite::params![
+                task.title,
+                task.description,
+                task.status,
+                task.phase,
+                task.priority,
+                task.tag,
+                task.assignee,
+                task.is_reworked.map(|v| if v { 1 } else { 0 }),
+                task.ready_for_review.map(|v| if v { 1 } else { 0 }),
+                task.id,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn delete_task(&self, id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM tasks WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn update_task_status(&self, id: &str, status: &str) -> Result<(), String> {
+        if !TASK_STATUSES.contains(&status) {
+            return Err(format!("Invalid status: {}", status));
+        }
+
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let updated = conn
+            .execute(
+                "UPDATE tasks SET status = ?1,
+                 ready_for_review = CASE WHEN status = ?1 THEN ready_for_review ELSE 0 END,
+                 updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                [status, id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        if updated == 0 {
+            return Err(format!("Task not found: {}", id));
+        }
+        Ok(())
+    }
+
+    /// Ends a review round: no task stays marked once work goes back to another role.
+    pub fn clear_ready_for_review(&self) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE tasks SET ready_for_review = 0 WHERE ready_for_review = 1",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Applies an agent's progress report: status, optional review mark and comment,
+    /// and its `MCP_TASK_UPDATE` activity entry, all in one transaction.
+    pub fn update_mission(
+        &self,
+        id: &str,
+        status: &str,
+        ready_for_review: Option<bool>,
+        author: &str,
+        comment: Option<&str>,
+        activity: &str,
+    ) -> Result<(), String> {
+        if !TASK_STATUSES.contains(&status) {
+            return Err(format!("Invalid status: {}", status));
+        }
+
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let updated = tx
+            .execute(
+                "UPDATE tasks SET status = ?1,
+                 ready_for_review = COALESCE(?2, CASE WHEN status = ?1 THEN ready_for_review ELSE 0 END),
+                 updated_at = CURRENT_TIMESTAMP WHERE id = ?3",
+                rusqlite::params![status, ready_for_review, id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Task not found: {}", id));
+        }
+        if let Some(comment) = comment {
+            tx.execute(
+                "INSERT INTO task_comments (task_id, author, content) VALUES (?1, ?2, ?3)",
+                [id, author, comment],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.execute(
+            "INSERT INTO system_activity (event_type, message) VALUES ('MCP_TASK_UPDATE', ?1)",
+            [activity],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn add_task_comment(
+        &self,
+        task_id: &str,
+        author: Option<&str>,
+        content: &str,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO task_comments (task_id, author, content) VALUES (?1, ?2, ?3)",
+            rusqlite::params![task_id, author, content],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn get_task_comments(&self, task_id: &str) -> Result<Vec<CommentData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT id, task_id, author, content, created_at FROM task_comments WHERE task_id = ?1 ORDER BY created_at, id")
+            .map_err(|e| e.to_string())?;
+
+        let comment_iter = stmt
+            .query_map([task_id], |row| {
+                Ok(CommentData {
+                    id: row.get(0)?,
+                    task_id: row.get(1)?,
+                    author: row.get(2)?,
+                    content: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let comments: Result<Vec<_>, _> = comment_iter.collect();
+        comments.map_err(|e| e.to_string())
+    }
+
+    pub fn get_roles(&self) -> Result<Vec<RoleData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, agent_name, role_type, system_prompt, is_default, allowed_tools, allowed_resources, base_role FROM roles ORDER BY is_default DESC, created_at"
+        ).map_err(|e| e.to_string())?;
+
+        let role_iter = stmt
+            .query_map([], |row| {
+                Ok(RoleData {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    agent_name: row.get(2)?,
+                    role_type: row.get(3)?,
+                    system_prompt: row.get(4)?,
+                    is_default: row.get::<_, i32>(5)? != 0,
+                    allowed_tools: parse_list(row.get(6)?),
+                    allowed_resources: parse_list(row.get(7)?),
+                    base_role: row.get(8)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let roles: Result<Vec<_>, _> = role_iter.collect();
+        roles.map_err(|e| e.to_string())
+    }
+
+    /// Sets a role's MCP allowlists; `None` restores the built-in policy.
+    pub fn set_role_permissions(
+        &self,
+        id: &str,
+        allowed_tools: Option<&[String]>,
+        allowed_resources: Option<&[String]>,
+    ) -> Result<(), String> {
+        let to_json = |list: Option<&[String]>| list.map(|l| serde_json::json!(l).to_string());
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let updated = conn
+            .execute(
+                "UPDATE roles SET allowed_tools = ?1, allowed_resources = ?2 WHERE id = ?3",
+                rusqlite::params![to_json(allowed_tools), to_json(allowed_resources), id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Role not found: {}", id));
+        }
+        Ok(())
+    }
+
+    pub fn get_project_spec(&self) -> Result<Option<SpecData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT id, name, overview, tech_stack, data_structure, features, design, rules FROM project_spec WHERE id = 'default'")
+            .map_err(|e| e.to_string())?;
+
+        let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+
+        if let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            Ok(Some(SpecData {
+                id: row.get(0).map_err(|e| e.to_string())?,
+                name: row.get(1).map_err(|e| e.to_string())?,
+                overview: row.get(2).map_err(|e| e.to_string())?,
+                tech_stack: row.get(3).map_err(|e| e.to_string())?,
+                data_structure: row.get(4).map_err(|e| e.to_string())?,
+                features: row.get(5).map_err(|e| e.to_string())?,
+                design: row.get(6).map_err(|e| e.to_string())?,
+                rules: row.get(7).map_err(|e| e.to_string())?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT value FROM settings WHERE key = ?1")
+            .map_err(|e| e.to_string())?;
+        let mut rows = stmt.query([key]).map_err(|e| e.to_string())?;
+
+        if let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            Ok(Some(row.get(0).map_err(|e| e.to_string())?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn log_activity(&self, event_type: &str, message: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO system_activity (event_type, message) VALUES (?1, ?2)",
+            [event_type, message],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub fn get_activity(&self) -> Result<Vec<ActivityData>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT id, event_type, message, user_id, timestamp FROM system_activity ORDER BY timestamp DESC LIMIT 100")
+            .map_err(|e| e.to_string())?;
+
+        let activity_iter = stmt
+            .query_map([], |row| {
+                Ok(ActivityData {
+                    id: row.get(0)?,
+                    event_type: row.get(1)?,
+                    message: row.get(2)?,
+                    user_id: row.get(3)?,
+                    timestamp: row.get(4)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let activities: Result<Vec<_>, _> = activity_iter.collect();
+        activities.map_err(|e| e.to_string())
+    }
+
+    pub fn load_app_state(&self) -> Result<StoredState, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let (state, resume, role_id, airlock_owner): (
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        ) = conn
+            .query_row(
+                "SELECT state, resume_state, role_id, airlock_owner FROM app_state WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(StoredState {
+            state: parse_enum(&state)?,
+            resume: resume.as_deref().map(parse_enum).transpose()?,
+            role_id,
+            airlock_owner,
+        })
+    }
+
+    /// Stores the new state and its history row atomically, but only if the persisted
+    /// state is still `transition.from` played by `from_role_id`. `Ok(false)` means
+    /// another process got there first.
+    pub fn commit_transition(
+        &self,
+        transition: &Transition,
+        from_role_id: Option<&str>,
+        resume: Option<AppState>,
+        airlock_owner: Option<&str>,
+    ) -> Result<bool, String> {
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let updated = tx
+            .execute(
+                "UPDATE app_state SET state = ?1, resume_state = ?2, role_id = ?3, airlock_owner = ?4,
+                 updated_at = ?5
+                 WHERE id = 1 AND state = ?6 AND role_id IS ?7",
+                rusqlite::params![
+                    enum_text(&transition.to),
+                    resume.as_ref().map(enum_text),
+                    transition.role_id,
+                    airlock_owner,
+                    transition.at,
+                    enum_text(&transition.from),
+                    from_role_id
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Ok(false);
+        }
+        tx.execute(
+            "INSERT INTO state_transitions (from_state, to_state, role_id, trigger, reason, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                enum_text(&transition.from),
+                enum_text(&transition.to),
+                transition.role_id,
+                enum_text(&transition.trigger),
+                transition.reason,
+                transition.at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    /// Marks this process as alive; see [`instance_alive`](Self::instance_alive).
+    pub fn heartbeat(&self, instance: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO instances (id, pid, heartbeat_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(id) DO UPDATE SET heartbeat_at = excluded.heartbeat_at",
+            rusqlite::params![
+                instance,
+                std::process::id(),
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Whether `instance` sent a heartbeat within `timeout`.
+    pub fn instance_alive(
+        &self,
+        instance: &str,
+        timeout: std::time::Duration,
+    ) -> Result<bool, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let heartbeat: Option<String> = conn
+            .query_row(
+                "SELECT heartbeat_at FROM instances WHERE id = ?1",
+                [instance],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let cutoff = chrono::Utc::now() - chrono::Duration::from_std(timeout).unwrap_or_default();
+        Ok(heartbeat
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+            .is_some_and(|at| at >= cutoff))
+    }
+
+    pub fn insert_approval(&self, request: &ApprovalRequest, owner: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO airlock_requests
+             (id, owner, tool, arguments, role, session_id, client, requested_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![
+                request.id,
+                owner,
+                request.tool,
+                request.arguments.to_string(),
+                enum_text(&request.role),
+                request.session_id,
+                request.client,
+                request.requested_at,
+                request.expires_at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Undecided requests of every process, oldest first.
+    pub fn pending_approvals(&self) -> Result<Vec<ApprovalRequest>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, tool, arguments, role, session_id, client, requested_at, expires_at
+                 FROM airlock_requests WHERE decision IS NULL ORDER BY requested_at",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, String>(7)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        rows.map(|row| {
+            let (id, tool, arguments, role, session_id, client, requested_at, expires_at) =
+                row.map_err(|e| e.to_string())?;
+            Ok(ApprovalRequest {
+                id,
+                tool,
+                arguments: serde_json::from_str(&arguments).unwrap_or_default(),
+                role: parse_enum(&role)?,
+                session_id,
+                client,
+                requested_at,
+                expires_at,
+            })
+        })
+        .collect()
+    }
+
+    pub fn count_pending_approvals(&self) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.query_row(
+            "SELECT COUNT(*) FROM airlock_requests WHERE decision IS NULL",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// Records a decision; `Ok(false)` if the request is gone or already decided.
+    pub fn decide_approval(&self, id: &str, decision: &Decision) -> Result<bool, String> {
+        let (decision, reason) = match decision {
+            Decision::Approved => ("approved", None),
+            Decision::Rejected(reason) => ("rejected", reason.as_deref()),
+        };
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let updated = conn
+            .execute(
+                "UPDATE airlock_requests SET decision = ?1, reason = ?2
+                 WHERE id = ?3 AND decision IS NULL",
+                rusqlite::params![decision, reason, id],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(updated > 0)
+    }
+
+    /// `None` if the request is gone, `Some(None)` while it is undecided.
+    pub fn approval_decision(&self, id: &str) -> Result<Option<Option<Decision>>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let row: Option<(Option<String>, Option<String>)> = conn
+            .query_row(
+                "SELECT decision, reason FROM airlock_requests WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        Ok(row.map(|(decision, reason)| match decision.as_deref() {
+            Some("approved") => Some(Decision::Approved),
+            Some(_) => Some(Decision::Rejected(reason)),
+            None => None,
+        }))
+    }
+
+    pub fn remove_approval(&self, id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM airlock_requests WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Drops requests whose waiting process has not sent a heartbeat within `timeout`;
+    /// nobody is left to act on their decision.
+    pub fn discard_orphaned_approvals(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<usize, String> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::from_std(timeout).unwrap_or_default();
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM airlock_requests WHERE owner NOT IN
+             (SELECT id FROM instances WHERE heartbeat_at >= ?1)",
+            [cutoff.to_rfc3339()],
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// Most recent transitions first.
+    pub fn get_transitions(&self, limit: u32) -> Result<Vec<Transition>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT from_state, to_state, role_id, trigger, reason, created_at FROM state_transitions ORDER BY id DESC LIMIT ?1")
+            .map_err(|e| e.to_string())?;
+
+        let rows = stmt
+            .query_map([limit], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        rows.map(|row| {
+            let (from, to, role_id, trigger, reason, at) = row.map_err(|e| e.to_string())?;
+            Ok(Transition {
+                from: parse_enum(&from)?,
+                to: parse_enum(&to)?,
+                role_id,
+                trigger: parse_enum(&trigger)?,
+                reason,
+                at,
+            })
+        })
+        .collect()
+    }
+
+    /// Adds each record to its day's row for its accounting key, in one transaction.
+    pub fn add_token_usage(&self, records: &[UsageRecord]) -> Result<(), String> {
+        let mut conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for record in records {
+            tx.execute(
+                "INSERT INTO token_usage
+                    (day, source, session_id, role, task_id, provider, model, requests, input_tokens, output_tokens, cost_usd)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                 ON CONFLICT (day, source, session_id, role, task_id, provider, model) DO UPDATE SET
+                    requests = requests + excluded.requests,
+                    input_tokens = input_tokens + excluded.input_tokens,
+                    output_tokens = output_tokens + excluded.output_tokens,
+                    cost_usd = CASE
+                        WHEN excluded.cost_usd IS NULL THEN cost_usd
+                        ELSE COALESCE(cost_usd, 0) + excluded.cost_usd
+                    END",
+                rusqlite::params![
+                    record.day,
+                    record.source,
+                    record.session_id.as_deref().unwrap_or(""),
+                    record.role.map(|r| enum_text(&r)).unwrap_or_default(),
+                    record.task_id.as_deref().unwrap_or(""),
+                    record.provider,
+                    record.model,
+                    record.requests as i64,
+                    record.input_tokens as i64,
+                    record.output_tokens as i64,
+                    record.cost_usd
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /// Usage since `since` (`YYYY-MM-DD`), summed per day or per week (starting Monday)
+    /// and per `group_by`: `session`, `role`, `task`, `provider`, `model` or `source`.
+    pub fn get_usage_breakdown(
+        &self,
+        period: &str,
+        group_by: &str,
+        since: &str,
+    ) -> Result<Vec<UsageBucket>, String> {
+        let period_expr = match period {
+            "day" => "day",
+            "week" => "date(day, 'weekday 0', '-6 days')",
+            other => return Err(format!("Unknown period: {}", other)),
+        };
+        let key_expr = match group_by {
+            "session" => "session_id",
+            "role" => "role",
+            "task" => "task_id",
+            "provider" => "provider",
+            "model" => "CASE WHEN provider = '' THEN model ELSE provider || '/' || model END",
+            "source" => "source",
+            other => return Err(format!("Unknown grouping: {}", other)),
+        };
+
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} AS period, {} AS key, SUM(requests), SUM(input_tokens), SUM(output_tokens), SUM(cost_usd)
+                 FROM token_usage WHERE day >= ?1
+                 GROUP BY period, key ORDER BY period DESC, SUM(input_tokens + output_tokens) DESC",
+                period_expr, key_expr
+            ))
+            .map_err(|e| e.to_string())?;
+
+        let rows = stmt
+            .query_map([since], |row| {
+                Ok(UsageBucket {
+                    period: row.get(0)?,
+                    key: row.get(1)?,
+                    requests: row.get::<_, i64>(2)? as u64,
+                    input_tokens: row.get::<_, i64>(3)? as u64,
+                    output_tokens: row.get::<_, i64>(4)? as u64,
+                    cost_usd: row.get(5)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let buckets: Result<Vec<_>, _> = rows.collect();
+        buckets.map_err(|e| e.to_string())
+    }
+
+    pub fn begin_ai_request(&self, id: &str, provider: &str, model: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO ai_requests (id, provider, model, status, started_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                id,
+                provider,
+                model,
+                enum_text(&AiRequestStatus::Running),
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Records how a request ended; `output` is the partial answer when it did not complete.
+    pub fn finish_ai_request(
+        &self,
+        id: &str,
+        status: AiRequestStatus,
+        output: &str,
+        error: Option<&str>,
+        usage: Option<AiUsage>,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE ai_requests SET status = ?2, output = ?3, error = ?4, input_tokens = ?5, output_tokens = ?6, finished_at = ?7
+             WHERE id = ?1",
+            rusqlite::params![
+                id,
+                enum_text(&status),
+                output,
+                error,
+                usage.map(|u| u.input_tokens as i64),
+                usage.map(|u| u.output_tokens as i64),
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Stores a unit enum the way serde names it, e.g. `Coder` or `airlock`.
+fn enum_text<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+fn parse_enum<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string())).map_err(|e| e.to_string())
+}
+
+fn task_from_row(row: &rusqlite::Row) -> rusqlite::Result<TaskData> {
+    Ok(TaskData {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        description: row.get(2)?,
+        status: row.get(3)?,
+        phase: row.get(4)?,
+        priority: row.get(5)?,
+        tag: row.get(6)?,
+        assignee: row.get(7)?,
+        is_reworked: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+        ready_for_review: row.get::<_, Option<i32>>(9)?.map(|v| v != 0),
+    })
+}
+
+fn parse_list(value: Option<String>) -> Option<Vec<String>> {
+    value.and_then(|v| serde_json::from_str(&v).ok())
+}
@@ -13,32 +13,44 @@ mod utils;
 use state_machine::StateManager;
 use tauri::Manager;
 
+/// Serves MCP over stdin/stdout without a webview, so it also works on headless machines.
+/// When the desktop app is already running, requests are forwarded to it instead so both
+/// share one state. Returns once stdin reaches EOF.
 pub fn run_mcp_stdio() {
-    tauri::Builder::default()
-        .setup(|app| {
-            let handle = app.handle().clone();
-            let db_state = db::init(&handle).expect("Failed to init DB");
-            app.manage(db_state);
-            app.manage(StateManager::new());
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio runtime");
+    runtime.block_on(async {
+        if let Some(proxy) = mcp::proxy::DesktopProxy::connect().await {
+            eprintln!("TaskRails desktop detected, forwarding MCP stdio to it");
+            proxy.run().await;
+            return;
+        }
 
-            let handle_for_stdio = handle.clone();
-            tauri::async_runtime::spawn(async move {
-                mcp::stdio::start_stdio_server(handle_for_stdio).await;
-            });
-            Ok(())
-        })
-        .run(tauri::generate_context!())
-        .expect("error while running mcp-stdio");
+        let app_dir = db::app_data_dir().expect("Failed to resolve app data dir");
+        let db_state = db::open(&app_dir).expect("Failed to init DB");
+        let state = StateManager::restore(&db_state).expect("Failed to restore app state");
+        tokio::spawn(state.clone().follow(db_state.clone(), false));
+        let ctx = mcp::McpContext::headless(db_state, state);
+        tokio::spawn(ctx.tokens.clone().persist(ctx.db.clone()));
+
+        mcp::stdio::start_stdio_server(ctx).await;
+    });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(StateManager::new())
         .setup(|app| {
             let db_state = db::init(app.handle())?;
+            let state = StateManager::restore(&db_state)?;
+            state.release_orphaned_hold(&db_state)?;
+            tauri::async_runtime::spawn(state.clone().follow(db_state.clone(), true));
+            app.manage(std::sync::Arc::new(
+                mcp::token_monitor::TokenMonitor::from_settings(&db_state),
+            ));
             app.manage(db_state);
+            app.manage(state);
+            app.manage(utils::ai::AiRequests::new());
 
             let handle = app.handle().clone();
             // Spawn MCP SSE Server
@@ -52,18 +64,30 @@ pub fn run() {
             greet,
             // Task Commands
             commands::get_tasks,
+            commands::get_task,
             commands::create_task,
             commands::update_task,
             commands::delete_task,
             commands::delete_all_tasks,
             commands::update_task_status,
+            commands::add_task_comment,
+            commands::get_task_comments,
             // Role Commands
             commands::get_roles,
             commands::create_role,
             commands::update_role,
             commands::delete_role,
+            commands::set_role_permissions,
             // State Commands
             commands::set_role,
+            commands::get_state_history,
+            commands::get_mcp_sessions,
+            commands::reset_mcp_session,
+            commands::get_token_usage,
+            commands::get_usage_report,
+            // Airlock Commands
+            commands::get_airlock_requests,
+            commands::resolve_airlock_request,
             // Settings & Workspace Commands
             commands::get_setting,
             commands::set_setting,
@@ -75,10 +99,18 @@ pub fn run() {
             commands::log_activity,
             commands::get_activity,
             commands::execute_ai_chat,
+            commands::stream_ai_chat,
+            commands::cancel_ai_chat,
             commands::get_project_spec,
             commands::update_project_spec,
             commands::open_chat_window
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                app.state::<std::sync::Arc<mcp::token_monitor::TokenMonitor>>()
+                    .flush(&app.state::<db::DbState>());
+            }
+        });
 }
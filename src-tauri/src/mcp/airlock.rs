@@ -0,0 +1,328 @@
+//! The Airlock: agent writes wait here until a human approves or rejects them in the
+//! desktop app. While anything is pending the app sits in [`AppState::Airlock`] and
+//! returns to the previous role once the queue drains.
+//!
+//! The queue lives in the `airlock_requests` table, so a headless stdio server can
+//! hold calls that the desktop app approves, even if the app is started later.
+
+use crate::mcp::session::Session;
+use crate::mcp::McpContext;
+use crate::state_machine::{AppState, Trigger, SYNC_INTERVAL};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// `settings` key: `"false"` lets agent writes through without approval.
+pub const ENABLED_SETTING: &str = "airlock_enabled";
+/// `settings` key: seconds to wait for a decision before the call is rejected.
+pub const TIMEOUT_SETTING: &str = "airlock_timeout_secs";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+/// How often a held call checks for a decision made by another process.
+const DECISION_POLL: Duration = Duration::from_millis(500);
+
+/// A held tool call, as shown in the approval dialog.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalRequest {
+    pub id: String,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub role: AppState,
+    pub session_id: String,
+    pub client: Option<String>,
+    pub requested_at: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Decision {
+    Approved,
+    Rejected(Option<String>),
+}
+
+#[derive(Default)]
+pub struct Airlock {
+    /// Calls held by this process, woken directly when decided here.
+    waiting: Mutex<HashMap<String, oneshot::Sender<Decision>>>,
+    /// Requests the desktop UI has been shown.
+    announced: Mutex<HashSet<String>>,
+}
+
+impl Airlock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pending requests of every process, oldest first.
+    pub fn list(&self, ctx: &McpContext) -> Result<Vec<ApprovalRequest>, String> {
+        ctx.db.pending_approvals()
+    }
+
+    /// Records a human decision and wakes the waiting tool call, in whichever process
+    /// it runs.
+    pub fn resolve(&self, ctx: &McpContext, id: &str, decision: Decision) -> Result<(), String> {
+        if !ctx.db.decide_approval(id, &decision)? {
+            return Err(format!("Approval request {} is no longer pending", id));
+        }
+        if let Some(reply) = self.waiting.lock().unwrap().remove(id) {
+            let _ = reply.send(decision);
+        }
+        // Leave the Airlock now rather than when the waiting call wakes up
+        self.exit_if_drained(ctx);
+        Ok(())
+    }
+
+    /// Holds a tool call until it is approved. `Err` carries the message returned to
+    /// the agent when the call is rejected, times out or is discarded.
+    pub async fn hold(
+        &self,
+        ctx: &McpContext,
+        session: &Session,
+        tool: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), String> {
+        if ctx.db.get_setting(ENABLED_SETTING)?.as_deref() == Some("false") {
+            return Ok(());
+        }
+
+        let timeout = ctx
+            .db
+            .get_setting(TIMEOUT_SETTING)?
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let now = chrono::Utc::now();
+        let request = ApprovalRequest {
+            id: format!("APR-{}", uuid::Uuid::new_v4().simple()),
+            tool: tool.to_string(),
+            arguments: arguments.clone(),
+            role: session.role(),
+            session_id: session.id.clone(),
+            client: session.client().map(|c| c.name),
+            requested_at: now.to_rfc3339(),
+            expires_at: (now + chrono::Duration::from_std(timeout).unwrap_or_default())
+                .to_rfc3339(),
+        };
+        let id = request.id.clone();
+
+        let (reply, mut decision) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(id.clone(), reply);
+        let mut guard = HoldGuard {
+            airlock: self,
+            ctx,
+            id: id.clone(),
+            event: "AIRLOCK_CANCELLED",
+            message: format!("{} {} was abandoned by the agent", id, tool),
+        };
+        ctx.db.insert_approval(&request, ctx.state.instance())?;
+        self.enter(ctx, tool);
+        let _ = ctx.db.log_activity(
+            "AIRLOCK_REQUEST",
+            &format!("[{:?}] {} {} {}", request.role, id, tool, arguments),
+        );
+        self.announce(ctx, request);
+
+        let outcome = tokio::time::timeout(timeout, self.wait(ctx, &id, &mut decision)).await;
+        let (event, result) = match outcome {
+            Ok(Some(Decision::Approved)) => ("AIRLOCK_APPROVED", Ok(())),
+            Ok(Some(Decision::Rejected(reason))) => (
+                "AIRLOCK_REJECTED",
+                Err(match reason.filter(|r| !r.trim().is_empty()) {
+                    Some(reason) => format!("Airlock: '{}' was rejected: {}", tool, reason),
+                    None => format!("Airlock: '{}' was rejected by the reviewer", tool),
+                }),
+            ),
+            Ok(None) => (
+                "AIRLOCK_DISCARDED",
+                Err(format!(
+                    "Airlock: '{}' was discarded from the approval queue",
+                    tool
+                )),
+            ),
+            Err(_) => (
+                "AIRLOCK_TIMEOUT",
+                Err(format!(
+                    "Airlock: '{}' was not approved within {}s and was discarded{}",
+                    tool,
+                    timeout.as_secs(),
+                    if ctx.app.is_none() {
+                        "; approve agent writes in the TaskRails desktop app"
+                    } else {
+                        ""
+                    }
+                )),
+            ),
+        };
+        guard.event = event;
+        guard.message = match &result {
+            Ok(()) => format!("{} {}", id, tool),
+            Err(message) => format!("{} {}", id, message),
+        };
+        drop(guard);
+        result
+    }
+
+    /// Shows requests queued by other processes in the desktop UI and hides decided
+    /// ones, until the runtime stops. Runs in the desktop app only.
+    pub async fn watch(self: Arc<Self>, ctx: McpContext) {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pending = match ctx.db.pending_approvals() {
+                Ok(pending) => pending,
+                Err(e) => {
+                    eprintln!("Failed to load approval queue: {}", e);
+                    continue;
+                }
+            };
+            let ids: HashSet<String> = pending.iter().map(|r| r.id.clone()).collect();
+            let gone: Vec<String> = self
+                .announced
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|id| !ids.contains(*id))
+                .cloned()
+                .collect();
+            for id in gone {
+                self.announced.lock().unwrap().remove(&id);
+                ctx.emit("airlock-resolved", &id);
+            }
+            for request in pending {
+                self.announce(&ctx, request);
+            }
+        }
+    }
+
+    /// Waits for a decision made here or, by polling, in another process. `None`
+    /// means the request left the queue without one.
+    async fn wait(
+        &self,
+        ctx: &McpContext,
+        id: &str,
+        reply: &mut oneshot::Receiver<Decision>,
+    ) -> Option<Decision> {
+        let mut poll = tokio::time::interval(DECISION_POLL);
+        loop {
+            tokio::select! {
+                decision = &mut *reply => return decision.ok(),
+                _ = poll.tick() => match ctx.db.approval_decision(id) {
+                    Ok(Some(None)) => {}
+                    Ok(Some(decision)) => return decision,
+                    Ok(None) => return None,
+                    Err(e) => eprintln!("Failed to check approval {}: {}", id, e),
+                },
+            }
+        }
+    }
+
+    fn announce(&self, ctx: &McpContext, request: ApprovalRequest) {
+        if self.announced.lock().unwrap().insert(request.id.clone()) {
+            ctx.emit("airlock-request", request);
+        }
+    }
+
+    fn enter(&self, ctx: &McpContext, tool: &str) {
+        let reason = Some(format!("{} awaiting approval", tool));
+        if let Err(e) = ctx
+            .state
+            .transition(&ctx.db, AppState::Airlock, Trigger::Airlock, reason)
+        {
+            eprintln!("Failed to enter Airlock: {}", e);
+        }
+    }
+
+    /// Returns to the held role once no process has a request pending; the state
+    /// machine remembers (and persists) which role that is.
+    fn exit_if_drained(&self, ctx: &McpContext) {
+        if ctx.state.get_state() != AppState::Airlock {
+            return;
+        }
+        match ctx.db.count_pending_approvals() {
+            Ok(0) => {}
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!("Failed to check approval queue: {}", e);
+                return;
+            }
+        }
+        let resume = ctx.state.resume_state().unwrap_or_default();
+        let reason = Some("approval queue drained".to_string());
+        if let Err(e) = ctx
+            .state
+            .transition(&ctx.db, resume, Trigger::Airlock, reason)
+        {
+            eprintln!("Failed to leave Airlock: {}", e);
+        }
+    }
+}
+
+/// Takes a held call out of the queue however `hold` ends, including when the agent
+/// disconnects and the tool call future is dropped mid-wait.
+struct HoldGuard<'a> {
+    airlock: &'a Airlock,
+    ctx: &'a McpContext,
+    id: String,
+    event: &'static str,
+    message: String,
+}
+
+impl Drop for HoldGuard<'_> {
+    fn drop(&mut self) {
+        // A timed-out or abandoned request is still in the queue
+        self.airlock.waiting.lock().unwrap().remove(&self.id);
+        if let Err(e) = self.ctx.db.remove_approval(&self.id) {
+            eprintln!("Failed to remove approval {}: {}", self.id, e);
+        }
+        self.airlock.exit_if_drained(self.ctx);
+        if self.airlock.announced.lock().unwrap().remove(&self.id) {
+            self.ctx.emit("airlock-resolved", &self.id);
+        }
+        let _ = self.ctx.db.log_activity(self.event, &self.message);
+        self.ctx
+            .sessions
+            .notify_resource_updated(crate::mcp::resources::ACTIVITY_URI);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::StateManager;
+
+    #[tokio::test]
+    async fn headless_hold_waits_for_a_decision() {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-airlock-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = crate::db::open(&dir).unwrap();
+        let ctx = McpContext::headless(db, StateManager::new());
+        let session = ctx.sessions.create("test", AppState::Coder);
+        let arguments = serde_json::json!({});
+
+        let approve = async {
+            loop {
+                if let Some(request) = ctx.db.pending_approvals().unwrap().pop() {
+                    assert_eq!(ctx.state.get_state(), AppState::Airlock);
+                    ctx.airlock
+                        .resolve(&ctx, &request.id, Decision::Approved)
+                        .unwrap();
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let (held, ()) = tokio::join!(
+            ctx.airlock.hold(&ctx, &session, "update_task", &arguments),
+            approve
+        );
+
+        assert_eq!(held, Ok(()));
+        assert_eq!(ctx.db.count_pending_approvals().unwrap(), 0);
+        assert_eq!(ctx.state.get_state(), AppState::Idle);
+    }
+}
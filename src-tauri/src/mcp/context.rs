@@ -0,0 +1,278 @@
+use crate::commands::{ActivityData, RoleData, SpecData, TaskData};
+use crate::mcp::McpContext;
+use crate::state_machine::AppState;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Default size budget for the rendered context, in characters.
+pub const DEFAULT_CONTEXT_BUDGET: usize = 12_000;
+
+const RECENT_ACTIVITY_LIMIT: usize = 10;
+
+/// Everything an agent needs to pick up work under the active role.
+pub struct ContextSnapshot {
+    pub state: AppState,
+    pub role: Option<RoleData>,
+    pub spec: Option<SpecData>,
+    pub active_tasks: Vec<TaskData>,
+    pub board: BTreeMap<String, usize>,
+    pub total_tasks: usize,
+    pub recent_activity: Vec<ActivityData>,
+}
+
+/// How aggressively the snapshot is condensed to fit the budget.
+#[derive(Debug, Clone, Copy)]
+struct Detail {
+    level: u8,
+    description_chars: Option<usize>,
+    spec_field_chars: usize,
+    max_tasks: usize,
+    activity: usize,
+}
+
+const DETAIL_LEVELS: [Detail; 4] = [
+    Detail {
+        level: 0,
+        description_chars: None,
+        spec_field_chars: usize::MAX,
+        max_tasks: usize::MAX,
+        activity: RECENT_ACTIVITY_LIMIT,
+    },
+    Detail {
+        level: 1,
+        description_chars: Some(400),
+        spec_field_chars: 800,
+        max_tasks: 30,
+        activity: RECENT_ACTIVITY_LIMIT,
+    },
+    Detail {
+        level: 2,
+        description_chars: Some(120),
+        spec_field_chars: 300,
+        max_tasks: 15,
+        activity: 5,
+    },
+    Detail {
+        level: 3,
+        description_chars: Some(0),
+        spec_field_chars: 120,
+        max_tasks: 5,
+        activity: 3,
+    },
+];
+
+pub fn build(ctx: &McpContext) -> Result<ContextSnapshot, String> {
+    let state = ctx.state.get_state();
+    let db_state = &ctx.db;
+
+    // A selected custom role stands in for the state's default row
+    let role_id = match state {
+        AppState::Idle | AppState::Airlock => None,
+        _ => ctx.state.active_role_id(),
+    };
+    let role = match &role_id {
+        Some(role_id) => db_state.get_roles()?.into_iter().find(|r| &r.id == role_id),
+        None => None,
+    };
+    let spec = db_state.get_project_spec()?;
+    let tasks = db_state.get_tasks()?;
+    let mut recent_activity = db_state.get_activity()?;
+    recent_activity.truncate(RECENT_ACTIVITY_LIMIT);
+
+    let mut board = BTreeMap::new();
+    for task in &tasks {
+        *board.entry(task.status.clone()).or_insert(0) += 1;
+    }
+    let total_tasks = tasks.len();
+
+    // Idle and Airlock have no role of their own, so they see every task in progress.
+    let active_tasks = tasks
+        .into_iter()
+        .filter(|t| t.status == "doing")
+        .filter(|t| match &role_id {
+            Some(role_id) => t.assignee.as_ref() == Some(role_id),
+            None => true,
+        })
+        .collect();
+
+    Ok(ContextSnapshot {
+        state,
+        role,
+        spec,
+        active_tasks,
+        board,
+        total_tasks,
+        recent_activity,
+    })
+}
+
+/// Renders the snapshot as a readable text block and a JSON document, condensing
+/// task descriptions, the spec and the activity log until the text fits `budget`.
+pub fn render(snapshot: &ContextSnapshot, budget: usize) -> (String, serde_json::Value) {
+    let mut rendered = (String::new(), serde_json::Value::Null);
+    for detail in DETAIL_LEVELS {
+        rendered = (render_text(snapshot, detail), render_json(snapshot, detail));
+        if rendered.0.chars().count() <= budget {
+            break;
+        }
+    }
+
+    // Even the tersest level can exceed a tiny budget; cut it rather than overflow.
+    if rendered.0.chars().count() > budget {
+        rendered.0 = truncate(&rendered.0, budget);
+    }
+    rendered
+}
+
+fn render_text(snapshot: &ContextSnapshot, detail: Detail) -> String {
+    let mut out = String::new();
+
+    out.push_str(&format!("# 目前角色: {:?}\n", snapshot.state));
+    if let Some(role) = &snapshot.role {
+        out.push_str(&format!("代理人: {} ({})\n", role.agent_name, role.name));
+        if let Some(prompt) = &role.system_prompt {
+            out.push_str(&format!("\n## 角色指令\n{}\n", prompt));
+        }
+    }
+
+    if let Some(spec) = &snapshot.spec {
+        out.push_str("\n## 專案規格\n");
+        for (_, label, value) in spec_fields(spec) {
+            if let Some(value) = value {
+                out.push_str(&format!(
+                    "### {}\n{}\n",
+                    label,
+                    truncate(value, detail.spec_field_chars)
+                ));
+            }
+        }
+    }
+
+    out.push_str(&format!("\n## 看板概況 (共 {} 項)\n", snapshot.total_tasks));
+    for (status, count) in &snapshot.board {
+        out.push_str(&format!("- {}: {}\n", status, count));
+    }
+
+    out.push_str(&format!(
+        "\n## 進行中任務 ({})\n",
+        snapshot.active_tasks.len()
+    ));
+    for task in snapshot.active_tasks.iter().take(detail.max_tasks) {
+        out.push_str(&format!(
+            "- [{}] {} ({})\n",
+            task.id,
+            task.title,
+            task.phase.as_deref().unwrap_or("-")
+        ));
+        if let Some(description) = describe(task, detail) {
+            for line in description.lines() {
+                out.push_str(&format!("  {}\n", line));
+            }
+        }
+    }
+    let hidden = snapshot.active_tasks.len().saturating_sub(detail.max_tasks);
+    if hidden > 0 {
+        out.push_str(&format!(
+            "- ... 另有 {} 項任務，請使用 list_tasks 查詢\n",
+            hidden
+        ));
+    }
+
+    if !snapshot.recent_activity.is_empty() {
+        out.push_str("\n## 最近活動\n");
+        for activity in snapshot.recent_activity.iter().take(detail.activity) {
+            out.push_str(&format!(
+                "- {} [{}] {}\n",
+                activity.timestamp,
+                activity.event_type,
+                truncate(&activity.message, 160)
+            ));
+        }
+    }
+
+    if detail.level > 0 {
+        out.push_str("\n(內容已摘要以符合大小限制，完整資料請使用 get_task / list_tasks)\n");
+    }
+    out
+}
+
+fn render_json(snapshot: &ContextSnapshot, detail: Detail) -> serde_json::Value {
+    let spec = snapshot.spec.as_ref().map(|spec| {
+        spec_fields(spec)
+            .into_iter()
+            .filter_map(|(key, _, value)| {
+                value.map(|v| (key.to_string(), json!(truncate(v, detail.spec_field_chars))))
+            })
+            .collect::<serde_json::Map<_, _>>()
+    });
+
+    let active_tasks: Vec<_> = snapshot
+        .active_tasks
+        .iter()
+        .take(detail.max_tasks)
+        .map(|task| {
+            json!({
+                "id": task.id,
+                "title": task.title,
+                "phase": task.phase,
+                "priority": task.priority,
+                "tag": task.tag,
+                "assignee": task.assignee,
+                "description": describe(task, detail),
+            })
+        })
+        .collect();
+
+    json!({
+        "state": snapshot.state,
+        "role": snapshot.role.as_ref().map(|role| json!({
+            "id": role.id,
+            "name": role.name,
+            "agent_name": role.agent_name,
+            "system_prompt": role.system_prompt,
+        })),
+        "spec": spec,
+        "board": {
+            "total": snapshot.total_tasks,
+            "by_status": snapshot.board,
+        },
+        "active_tasks": active_tasks,
+        "active_tasks_omitted": snapshot.active_tasks.len().saturating_sub(detail.max_tasks),
+        "recent_activity": snapshot.recent_activity.iter().take(detail.activity).collect::<Vec<_>>(),
+        "summarized": detail.level > 0,
+    })
+}
+
+fn spec_fields(spec: &SpecData) -> [(&'static str, &'static str, Option<&String>); 7] {
+    [
+        ("name", "名稱", spec.name.as_ref()),
+        ("overview", "概述", spec.overview.as_ref()),
+        ("tech_stack", "技術棧", spec.tech_stack.as_ref()),
+        ("data_structure", "資料結構", spec.data_structure.as_ref()),
+        ("features", "功能", spec.features.as_ref()),
+        ("design", "設計", spec.design.as_ref()),
+        ("rules", "規則", spec.rules.as_ref()),
+    ]
+}
+
+fn describe(task: &TaskData, detail: Detail) -> Option<String> {
+    let description = task
+        .description
+        .as_deref()
+        .filter(|d| !d.trim().is_empty())?;
+    match detail.description_chars {
+        Some(0) => None,
+        Some(limit) => Some(truncate(description, limit)),
+        None => Some(description.to_string()),
+    }
+}
+
+/// Cuts `text` to at most `limit` characters (not bytes), marking the cut.
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(limit.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
@@ -0,0 +1,248 @@
+//! Hello Protocol: after a role switch each agent is told who it is now and must
+//! acknowledge it, either by calling `acknowledge_role` or by echoing the role's
+//! banner in its next tool call. Sessions that carry on under the old role are
+//! flagged in the activity log and the desktop UI.
+
+use crate::db::DbState;
+use crate::mcp::session::{Session, SessionManager};
+use crate::mcp::McpContext;
+use crate::state_machine::{AppState, Transition, Trigger};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// `settings` key: `"false"` turns the handshake off.
+pub const ENABLED_SETTING: &str = "hello_protocol";
+/// `settings` key holding the UI language, e.g. `zh-TW`.
+pub const LANGUAGE_SETTING: &str = "language";
+/// `settings` key prefix for a custom banner, e.g. `hello_banner_reviewer`.
+pub const BANNER_SETTING_PREFIX: &str = "hello_banner_";
+pub const ACK_TOOL: &str = "acknowledge_role";
+
+/// The handshake a session still owes for its latest role switch.
+#[derive(Debug, Clone)]
+pub struct PendingHello {
+    pub from: AppState,
+    pub to: AppState,
+    pub banner: String,
+    pub prompt: String,
+    /// Whether the prompt has been attached to a response the agent has seen.
+    pub delivered: bool,
+    /// Set once the session has been reported for acting under the old role.
+    pub flagged: bool,
+}
+
+/// Payload of the `identity-mismatch` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentityMismatch {
+    pub session_id: String,
+    pub client: Option<String>,
+    pub expected: AppState,
+    pub previous: AppState,
+    pub tool: String,
+}
+
+/// Transition hook: queues the hello for every connected session.
+pub fn on_transition(db: &DbState, sessions: &Arc<SessionManager>, transition: &Transition) {
+    if transition.trigger == Trigger::Airlock {
+        return;
+    }
+    let Some(role) = transition.to.role_name() else {
+        return;
+    };
+    if db.get_setting(ENABLED_SETTING).ok().flatten().as_deref() == Some("false") {
+        return;
+    }
+
+    let language = db
+        .get_setting(LANGUAGE_SETTING)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let banner = db
+        .get_setting(&format!("{}{}", BANNER_SETTING_PREFIX, role))
+        .ok()
+        .flatten()
+        .filter(|b| !b.trim().is_empty())
+        .unwrap_or_else(|| default_banner(&language, transition.to).to_string());
+    let prompt = prompt(&language, role, &banner);
+
+    for session in sessions.all() {
+        session.set_pending_hello(PendingHello {
+            from: transition.from,
+            to: transition.to,
+            banner: banner.clone(),
+            prompt: prompt.clone(),
+            delivered: false,
+            flagged: false,
+        });
+    }
+}
+
+/// Checks a `tools/call` against the outstanding handshake before it runs.
+pub fn check_tool_call(ctx: &McpContext, session: &Session, tool: &str, args: &serde_json::Value) {
+    let Some(mut hello) = session.pending_hello() else {
+        return;
+    };
+
+    if acknowledges(&hello, tool, args) {
+        session.clear_pending_hello();
+        let _ = ctx.db.log_activity(
+            "HELLO_ACK",
+            &format!(
+                "[{}] {} acknowledged {:?}",
+                session.transport,
+                client_name(session),
+                hello.to
+            ),
+        );
+        return;
+    }
+
+    // The first call may predate the prompt; only calls made after seeing it count.
+    if !hello.delivered || hello.flagged {
+        return;
+    }
+    hello.flagged = true;
+    session.set_pending_hello(hello.clone());
+
+    let _ = ctx.db.log_activity(
+        "ROLE_MISMATCH",
+        &format!(
+            "[{}] {} called {} without acknowledging {:?}; still acting as {:?}",
+            session.transport,
+            client_name(session),
+            tool,
+            hello.to,
+            hello.from
+        ),
+    );
+    ctx.emit(
+        "identity-mismatch",
+        IdentityMismatch {
+            session_id: session.id.clone(),
+            client: session.client().map(|c| c.name),
+            expected: hello.to,
+            previous: hello.from,
+            tool: tool.to_string(),
+        },
+    );
+}
+
+/// Appends the hello prompt to a `tools/call` or `prompts/get` result until the
+/// session acknowledges its new role.
+pub fn inject(session: &Session, result: &mut serde_json::Value) {
+    let Some(mut hello) = session.pending_hello() else {
+        return;
+    };
+    let target = if result.pointer("/content/0/text").is_some() {
+        "/content/0/text"
+    } else {
+        "/messages/0/content/text"
+    };
+    if let Some(serde_json::Value::String(text)) = result.pointer_mut(target) {
+        text.push_str(&format!("\n\n{}", hello.prompt));
+        hello.delivered = true;
+        session.set_pending_hello(hello);
+    }
+}
+
+fn acknowledges(hello: &PendingHello, tool: &str, args: &serde_json::Value) -> bool {
+    if tool == ACK_TOOL {
+        return args.get("role").and_then(|r| r.as_str()) == hello.to.role_name();
+    }
+    let banner = normalize(&hello.banner);
+    !banner.is_empty() && mentions(args, &banner)
+}
+
+/// Whether any string argument contains `needle` (compared without Markdown emphasis).
+fn mentions(value: &serde_json::Value, needle: &str) -> bool {
+    match value {
+        serde_json::Value::String(s) => normalize(s).contains(needle),
+        serde_json::Value::Array(items) => items.iter().any(|v| mentions(v, needle)),
+        serde_json::Value::Object(map) => map.values().any(|v| mentions(v, needle)),
+        _ => false,
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.replace('*', "").trim().to_string()
+}
+
+fn client_name(session: &Session) -> String {
+    session
+        .client()
+        .map(|c| c.name)
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Built-in banners; the UI language picks the variant, Traditional Chinese by default.
+fn default_banner(language: &str, role: AppState) -> &'static str {
+    let [coder, reviewer, architect] = match language {
+        "zh-CN" => [
+            "🛠️ **开发模式启动**",
+            "🛑 **审查模式启动**",
+            "📐 **架构模式启动**",
+        ],
+        "en-US" => [
+            "🛠️ **Coder Mode Engaged**",
+            "🛑 **Review Mode Engaged**",
+            "📐 **Architect Mode Engaged**",
+        ],
+        "ja-JP" => [
+            "🛠️ **開発モード起動**",
+            "🛑 **レビューモード起動**",
+            "📐 **設計モード起動**",
+        ],
+        "es-ES" => [
+            "🛠️ **Modo Desarrollo Activado**",
+            "🛑 **Modo Revisión Activado**",
+            "📐 **Modo Arquitecto Activado**",
+        ],
+        "fr-FR" => [
+            "🛠️ **Mode Développement Activé**",
+            "🛑 **Mode Revue Activé**",
+            "📐 **Mode Architecte Activé**",
+        ],
+        "de-DE" => [
+            "🛠️ **Entwicklungsmodus Aktiv**",
+            "🛑 **Review-Modus Aktiv**",
+            "📐 **Architekturmodus Aktiv**",
+        ],
+        _ => [
+            "🛠️ **開發模式啟動**",
+            "🛑 **審查模式啟動**",
+            "📐 **架構模式啟動**",
+        ],
+    };
+    match role {
+        AppState::Reviewer => reviewer,
+        AppState::Architect => architect,
+        _ => coder,
+    }
+}
+
+fn prompt(language: &str, role: &str, banner: &str) -> String {
+    match language {
+        "zh-CN" => format!(
+            "【身份变更】你现在的角色是 {role}。下一则回复的第一行必须是：{banner}\n并在使用其他工具前调用 `{ACK_TOOL}` (role: \"{role}\")。"
+        ),
+        "en-US" => format!(
+            "[IDENTITY CHANGE] Your role is now {role}. Start your next reply with exactly: {banner}\nThen call `{ACK_TOOL}` (role: \"{role}\") before using any other tool."
+        ),
+        "ja-JP" => format!(
+            "【ロール変更】現在のロールは {role} です。次の返信の1行目は必ず次のようにしてください：{banner}\n他のツールを使う前に `{ACK_TOOL}` (role: \"{role}\") を呼び出してください。"
+        ),
+        "es-ES" => format!(
+            "[CAMBIO DE IDENTIDAD] Tu rol ahora es {role}. Empieza tu próxima respuesta exactamente con: {banner}\nLuego llama a `{ACK_TOOL}` (role: \"{role}\") antes de usar cualquier otra herramienta."
+        ),
+        "fr-FR" => format!(
+            "[CHANGEMENT D'IDENTITÉ] Ton rôle est maintenant {role}. Commence ta prochaine réponse exactement par : {banner}\nPuis appelle `{ACK_TOOL}` (role: \"{role}\") avant tout autre outil."
+        ),
+        "de-DE" => format!(
+            "[IDENTITÄTSWECHSEL] Deine Rolle ist jetzt {role}. Beginne deine nächste Antwort genau mit: {banner}\nRufe dann `{ACK_TOOL}` (role: \"{role}\") auf, bevor du ein anderes Tool verwendest."
+        ),
+        _ => format!(
+            "【身分變更】你現在的角色是 {role}。下一則回覆的第一行必須是：{banner}\n並在使用其他工具前呼叫 `{ACK_TOOL}` (role: \"{role}\")。"
+        ),
+    }
+}
@@ -0,0 +1,368 @@
+//! JSON-RPC 2.0 framing shared by every MCP transport: parsing, request validation,
+//! batches and notifications. Transports hand raw message bodies to [`handle_message`]
+//! and write back whatever it returns.
+
+use crate::mcp::session::Session;
+use crate::mcp::token_monitor;
+use crate::mcp::McpContext;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Option<Value>,
+    /// `None` for notifications. A request with `"id": null` keeps `Some(Value::Null)`.
+    pub id: Option<Value>,
+}
+
+/// Exactly one of `result` and `error` is set. `id` is always serialized, as `null`
+/// when the request's id could not be determined.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+    pub id: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonRpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcRequest {
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+}
+
+impl JsonRpcResponse {
+    pub fn success(id: Option<Value>, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    pub fn failure(id: Option<Value>, error: JsonRpcError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+}
+
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(INTERNAL_ERROR, message)
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+}
+
+/// Parses a message body. Malformed JSON yields the `-32700` error to send back,
+/// with a `null` id since none could be read.
+pub fn parse(body: &str) -> Result<Value, JsonRpcError> {
+    serde_json::from_str(body)
+        .map_err(|e| JsonRpcError::new(PARSE_ERROR, format!("Parse error: {}", e)))
+}
+
+/// Validates one message of a (possibly batched) body as a request or notification.
+fn parse_request(value: Value) -> Result<JsonRpcRequest, Box<JsonRpcResponse>> {
+    let Value::Object(mut object) = value else {
+        return Err(invalid_request(None, "Request must be an object"));
+    };
+
+    let id = match object.remove("id") {
+        None => None,
+        Some(id @ (Value::String(_) | Value::Number(_) | Value::Null)) => Some(id),
+        Some(_) => return Err(invalid_request(None, "id must be a string, number or null")),
+    };
+    if object.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+        return Err(invalid_request(id, "jsonrpc must be \"2.0\""));
+    }
+    let Some(Value::String(method)) = object.remove("method") else {
+        return Err(invalid_request(id, "method must be a string"));
+    };
+    let params = match object.remove("params") {
+        None => None,
+        Some(params @ (Value::Object(_) | Value::Array(_))) => Some(params),
+        Some(_) => return Err(invalid_request(id, "params must be an object or array")),
+    };
+
+    Ok(JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method,
+        params,
+        id,
+    })
+}
+
+fn invalid_request(id: Option<Value>, message: &str) -> Box<JsonRpcResponse> {
+    Box::new(JsonRpcResponse::failure(
+        Some(id.unwrap_or(Value::Null)),
+        JsonRpcError::new(INVALID_REQUEST, format!("Invalid Request: {}", message)),
+    ))
+}
+
+/// Replies from the client to server-initiated requests carry no method.
+fn is_client_response(value: &Value) -> bool {
+    value.get("method").is_none() && (value.get("result").is_some() || value.get("error").is_some())
+}
+
+/// True if the parsed body contains at least one message that expects a response.
+/// Bodies with only notifications and client responses are merely acknowledged.
+pub fn expects_response(value: &Value) -> bool {
+    let expects = |v: &Value| !is_client_response(v) && (!v.is_object() || v.get("id").is_some());
+    match value {
+        Value::Array(items) => items.is_empty() || items.iter().any(expects),
+        other => expects(other),
+    }
+}
+
+/// Methods named in a body, for logging and per-method transport decisions.
+pub fn methods(value: &Value) -> Vec<&str> {
+    fn method(v: &Value) -> Option<&str> {
+        v.get("method").and_then(|m| m.as_str())
+    }
+    match value {
+        Value::Array(items) => items.iter().filter_map(method).collect(),
+        other => method(other).into_iter().collect(),
+    }
+}
+
+/// Handles a raw body and returns the serialized reply, or `None` when nothing
+/// must be sent (notifications, client responses, batches of only those).
+pub async fn handle_message(body: &str, ctx: &McpContext, session: &Session) -> Option<String> {
+    match parse(body) {
+        Ok(value) => dispatch(value, ctx, session).await,
+        Err(error) => Some(error_reply(error)),
+    }
+}
+
+/// Serializes a reply for an error that is not tied to any request id.
+pub fn error_reply(error: JsonRpcError) -> String {
+    serde_json::to_string(&JsonRpcResponse::failure(None, error)).unwrap()
+}
+
+/// Handles an already parsed body: a single message or a batch.
+pub async fn dispatch(value: Value, ctx: &McpContext, session: &Session) -> Option<String> {
+    match value {
+        Value::Array(items) if items.is_empty() => {
+            let response = invalid_request(None, "empty batch");
+            Some(serde_json::to_string(&response).unwrap())
+        }
+        Value::Array(items) => {
+            let responses: Vec<JsonRpcResponse> = futures::future::join_all(
+                items.into_iter().map(|item| handle_one(item, ctx, session)),
+            )
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+
+            // A batch of only notifications gets no reply at all, not an empty array.
+            if responses.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&responses).unwrap())
+            }
+        }
+        other => handle_one(other, ctx, session)
+            .await
+            .map(|response| serde_json::to_string(&response).unwrap()),
+    }
+}
+
+async fn handle_one(value: Value, ctx: &McpContext, session: &Session) -> Option<JsonRpcResponse> {
+    if is_client_response(&value) {
+        if let Ok(response) = serde_json::from_value(value) {
+            session.resolve(response);
+        }
+        return None;
+    }
+
+    let request = match parse_request(value) {
+        Ok(request) => request,
+        Err(response) => return Some(*response),
+    };
+
+    if request.is_notification() {
+        token_monitor::metered(request, ctx, session).await;
+        None
+    } else {
+        Some(token_monitor::metered(request, ctx, session).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::{AppState, StateManager};
+    use serde_json::json;
+
+    fn context() -> (McpContext, std::sync::Arc<Session>) {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-jsonrpc-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = crate::db::open(&dir).unwrap();
+        let ctx = McpContext::headless(db, StateManager::new());
+        let session = ctx.sessions.create("test", AppState::Idle);
+        (ctx, session)
+    }
+
+    async fn call(body: &str) -> Option<Value> {
+        let (ctx, session) = context();
+        handle_message(body, &ctx, &session)
+            .await
+            .map(|reply| serde_json::from_str(&reply).unwrap())
+    }
+
+    #[tokio::test]
+    async fn request_gets_result_without_error_member() {
+        let reply = call(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#)
+            .await
+            .unwrap();
+        assert_eq!(reply, json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+    }
+
+    #[tokio::test]
+    async fn error_response_has_no_result_member() {
+        let reply = call(r#"{"jsonrpc":"2.0","id":"a","method":"nope"}"#)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], "a");
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+        assert!(reply.get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn notification_gets_no_response() {
+        assert!(
+            call(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+                .await
+                .is_none()
+        );
+        assert!(call(r#"{"jsonrpc":"2.0","method":"nope"}"#).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn null_id_is_a_request() {
+        let reply = call(r#"{"jsonrpc":"2.0","id":null,"method":"ping"}"#)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["result"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_parse_error() {
+        let reply = call(r#"{"jsonrpc":"2.0","method":"ping""#).await.unwrap();
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        assert_eq!(reply["id"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_rejected() {
+        for body in [
+            r#"1"#,
+            r#"{"jsonrpc":"1.0","id":1,"method":"ping"}"#,
+            r#"{"jsonrpc":"2.0","id":1,"method":5}"#,
+            r#"{"jsonrpc":"2.0","id":1,"method":"ping","params":"x"}"#,
+            r#"{"jsonrpc":"2.0","id":{},"method":"ping"}"#,
+        ] {
+            let reply = call(body).await.unwrap();
+            assert_eq!(reply["error"]["code"], INVALID_REQUEST, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_request_keeps_valid_id() {
+        let reply = call(r#"{"jsonrpc":"1.0","id":7,"method":"ping"}"#)
+            .await
+            .unwrap();
+        assert_eq!(reply["id"], 7);
+    }
+
+    #[tokio::test]
+    async fn empty_batch_is_invalid() {
+        let reply = call("[]").await.unwrap();
+        assert!(reply.is_object());
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn batch_answers_requests_only() {
+        let reply = call(
+            r#"[
+                {"jsonrpc":"2.0","id":1,"method":"ping"},
+                {"jsonrpc":"2.0","method":"notifications/initialized"},
+                {"jsonrpc":"2.0","id":2,"method":"nope"},
+                1
+            ]"#,
+        )
+        .await
+        .unwrap();
+
+        let replies = reply.as_array().unwrap();
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(replies[2]["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn batch_of_notifications_gets_no_response() {
+        let body = r#"[
+            {"jsonrpc":"2.0","method":"notifications/initialized"},
+            {"jsonrpc":"2.0","id":9,"result":{}}
+        ]"#;
+        assert!(call(body).await.is_none());
+        assert!(!expects_response(&parse(body).unwrap()));
+    }
+
+    #[test]
+    fn expects_response_detects_requests() {
+        assert!(expects_response(
+            &json!({"jsonrpc":"2.0","id":1,"method":"ping"})
+        ));
+        assert!(expects_response(&json!([])));
+        assert!(!expects_response(
+            &json!({"jsonrpc":"2.0","method":"notifications/initialized"})
+        ));
+    }
+}
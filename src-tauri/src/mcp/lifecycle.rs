@@ -0,0 +1,117 @@
+//! MCP initialization: protocol version negotiation and what each client declared.
+
+use crate::mcp::session::{ClientInfo, Session};
+use crate::mcp::McpContext;
+use serde_json::json;
+use std::time::Duration;
+
+/// Protocol revisions this server speaks, newest first.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const ROOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Echoes the client's revision when supported, otherwise offers our newest one
+/// and leaves it to the client to disconnect if it cannot speak it.
+pub fn negotiate_version(requested: Option<&str>) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .copied()
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
+pub fn initialize(
+    ctx: &McpContext,
+    session: &Session,
+    params: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let params = params.cloned().unwrap_or(json!({}));
+    let requested = params.get("protocolVersion").and_then(|v| v.as_str());
+    let version = negotiate_version(requested);
+
+    let client_info = params.get("clientInfo");
+    let client = ClientInfo {
+        name: client_info
+            .and_then(|c| c.get("name"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        version: client_info
+            .and_then(|c| c.get("version"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string()),
+        protocol_version: version.to_string(),
+        capabilities: params.get("capabilities").cloned().unwrap_or(json!({})),
+    };
+
+    let capabilities = client.capability_names();
+    let message = format!(
+        "[{}] {}{} connected (protocol {}{}), capabilities: {}",
+        session.transport,
+        client.name,
+        client
+            .version
+            .as_deref()
+            .map(|v| format!(" {}", v))
+            .unwrap_or_default(),
+        version,
+        match requested {
+            Some(requested) if requested != version => format!(", requested {}", requested),
+            _ => String::new(),
+        },
+        if capabilities.is_empty() {
+            "none".to_string()
+        } else {
+            capabilities.join(", ")
+        }
+    );
+    if let Err(e) = ctx.db.log_activity("MCP_CLIENT_INIT", &message) {
+        eprintln!("Failed to log MCP client: {}", e);
+    }
+    session.set_client(client);
+
+    json!({
+        "protocolVersion": version,
+        "capabilities": {
+            "tools": { "listChanged": true },
+            "resources": { "subscribe": true, "listChanged": true },
+            "prompts": {},
+            "logging": {}
+        },
+        "serverInfo": {
+            "name": "TaskRails",
+            "version": "2.0.0"
+        }
+    })
+}
+
+/// `notifications/initialized` and `notifications/roots/list_changed`: asks clients
+/// that expose roots which workspace folders they have open.
+pub async fn refresh_roots(ctx: &McpContext, session: &Session) {
+    if !session.supports("roots") {
+        return;
+    }
+
+    match session
+        .request("roots/list", json!({}), ROOTS_TIMEOUT)
+        .await
+    {
+        Ok(result) => {
+            let roots = result
+                .get("roots")
+                .and_then(|r| r.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let uris: Vec<&str> = roots
+                .iter()
+                .filter_map(|r| r.get("uri").and_then(|u| u.as_str()))
+                .collect();
+            let _ = ctx.db.log_activity(
+                "MCP_CLIENT_ROOTS",
+                &format!("[{}] roots: {}", session.transport, uris.join(", ")),
+            );
+            session.set_roots(roots);
+        }
+        Err(e) => eprintln!("roots/list failed for session {}: {}", session.id, e),
+    }
+}
@@ -1,159 +1,241 @@
+pub mod airlock;
+pub mod context;
+pub mod hello;
+pub mod jsonrpc;
+pub mod lifecycle;
+pub mod permissions;
+pub mod prompts;
+pub mod proxy;
+pub mod reset;
+pub mod resources;
+pub mod session;
 pub mod sse;
 pub mod stdio;
+pub mod streamable_http;
 pub mod token_monitor;
+pub mod tools;
 
-use serde::{Deserialize, Serialize};
+use crate::db::DbState;
+use crate::mcp::airlock::Airlock;
+use crate::mcp::permissions::RolePolicy;
+use crate::mcp::session::{Session, SessionManager};
+use crate::mcp::token_monitor::TokenMonitor;
+use crate::mcp::tools::ToolRegistry;
+use crate::state_machine::StateManager;
+pub use jsonrpc::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+use serde::Serialize;
 use serde_json::json;
-use tauri::Manager;
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct JsonRpcRequest {
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: Option<serde_json::Value>,
-    pub id: Option<serde_json::Value>,
+/// What MCP handlers need from the host process. Built from the running desktop app,
+/// or standalone when the stdio server runs headless.
+#[derive(Clone)]
+pub struct McpContext {
+    pub db: DbState,
+    pub state: StateManager,
+    pub sessions: Arc<SessionManager>,
+    pub tools: Arc<ToolRegistry>,
+    pub airlock: Arc<Airlock>,
+    pub tokens: Arc<TokenMonitor>,
+    pub app: Option<tauri::AppHandle>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct JsonRpcResponse {
-    pub jsonrpc: String,
-    pub result: Option<serde_json::Value>,
-    pub error: Option<JsonRpcError>,
-    pub id: Option<serde_json::Value>,
-}
+impl McpContext {
+    pub fn from_app(handle: &tauri::AppHandle) -> Self {
+        Self::watch_state(Self {
+            db: handle.state::<DbState>().inner().clone(),
+            state: handle.state::<StateManager>().inner().clone(),
+            sessions: Arc::new(SessionManager::new()),
+            tools: Arc::new(ToolRegistry::builtin()),
+            airlock: Arc::new(Airlock::new()),
+            tokens: handle.state::<Arc<TokenMonitor>>().inner().clone(),
+            app: Some(handle.clone()),
+        })
+    }
+
+    pub fn headless(db: DbState, state: StateManager) -> Self {
+        let tokens = Arc::new(TokenMonitor::from_settings(&db));
+        Self::watch_state(Self {
+            db,
+            state,
+            sessions: Arc::new(SessionManager::new()),
+            tools: Arc::new(ToolRegistry::builtin()),
+            airlock: Arc::new(Airlock::new()),
+            tokens,
+            app: None,
+        })
+    }
+
+    /// Keeps sessions and the UI in step with every role transition, whoever caused it.
+    fn watch_state(self) -> Self {
+        let sessions = self.sessions.clone();
+        let db = self.db.clone();
+        let app = self.app.clone();
+        // Hooks also fire from Tauri commands, which run outside the MCP server's runtime
+        let runtime = tokio::runtime::Handle::try_current().ok();
+        self.state.on_transition(move |transition| {
+            sessions.broadcast_role(transition.to);
+            reset::on_transition(&db, &sessions, runtime.as_ref(), transition);
+            hello::on_transition(&db, &sessions, transition);
+            if let Some(app) = &app {
+                let _ = app.emit("state-changed", transition);
+            }
+        });
+        self
+    }
+
+    /// Tells subscribed clients that the board and the task's own resource changed.
+    pub fn notify_task_changed(&self, task_id: &str) {
+        self.sessions.notify_resource_updated(resources::BOARD_URI);
+        self.sessions
+            .notify_resource_updated(&resources::task_uri(task_id));
+    }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct JsonRpcError {
-    pub code: i32,
-    pub message: String,
-    pub data: Option<serde_json::Value>,
+    /// Forwards an event to the desktop UI; a no-op when running headless.
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Some(app) = &self.app {
+            let _ = app.emit(event, payload);
+        }
+    }
 }
 
-pub async fn handle_mcp_request(req: JsonRpcRequest, handle: &tauri::AppHandle) -> JsonRpcResponse {
+pub async fn handle_mcp_request(
+    req: JsonRpcRequest,
+    ctx: &McpContext,
+    session: &Session,
+) -> JsonRpcResponse {
     match req.method.as_str() {
-        "initialize" => JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {},
-                    "resources": {},
-                    "prompts": {}
-                },
-                "serverInfo": {
-                    "name": "TaskRails",
-                    "version": "2.0.0"
+        "initialize" => JsonRpcResponse::success(
+            req.id,
+            lifecycle::initialize(ctx, session, req.params.as_ref()),
+        ),
+        "notifications/initialized" | "notifications/roots/list_changed" => {
+            lifecycle::refresh_roots(ctx, session).await;
+            JsonRpcResponse::success(req.id, json!({}))
+        }
+        "ping" => JsonRpcResponse::success(req.id, json!({})),
+        "listTools" | "tools/list" => match RolePolicy::current(ctx) {
+            Ok(policy) => {
+                JsonRpcResponse::success(req.id, json!({ "tools": ctx.tools.definitions(&policy) }))
+            }
+            Err(e) => JsonRpcResponse::failure(req.id, JsonRpcError::internal(e)),
+        },
+        "callTool" | "tools/call" => {
+            let Some(params) = req.params else {
+                return JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params("Missing parameters for callTool"),
+                );
+            };
+            let Some(tool_name) = params.get("name").and_then(|v| v.as_str()) else {
+                return JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params("tools/call requires 'name'"),
+                );
+            };
+            let args = params.get("arguments").cloned().unwrap_or(json!({}));
+            let policy = match RolePolicy::current(ctx) {
+                Ok(policy) => policy,
+                Err(e) => return JsonRpcResponse::failure(req.id, JsonRpcError::internal(e)),
+            };
+
+            hello::check_tool_call(ctx, session, tool_name, &args);
+            match ctx.tools.call(tool_name, ctx, session, &policy, args).await {
+                Ok(mut result) => {
+                    hello::inject(session, &mut result);
+                    JsonRpcResponse::success(req.id, result)
                 }
-            })),
-            error: None,
-            id: req.id,
+                Err(error) => JsonRpcResponse::failure(req.id, error),
+            }
+        }
+        "prompts/list" => match prompts::list(ctx) {
+            Ok(result) => JsonRpcResponse::success(req.id, result),
+            Err(e) => JsonRpcResponse::failure(req.id, JsonRpcError::internal(e)),
         },
-        "listTools" | "tools/list" => JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: Some(json!({
-                "tools": [
-                    {
-                        "name": "get_context",
-                        "description": "獲取目前專案的上下文、當前角色與任務進度。",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {}
-                        }
-                    },
-                    {
-                        "name": "update_mission",
-                        "description": "更新特定任務的狀態或進度描述。",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "task_id": { "type": "string" },
-                                "status": { "type": "string", "enum": ["todo", "doing", "done"] },
-                                "comment": { "type": "string" }
-                            },
-                            "required": ["task_id", "status"]
-                        }
+        "prompts/get" => {
+            let params = req.params.clone().unwrap_or(json!({}));
+            let name = params.get("name").and_then(|v| v.as_str());
+            let args = params.get("arguments").cloned().unwrap_or(json!({}));
+            match name {
+                Some(name) => match prompts::get(ctx, name, &args) {
+                    Ok(mut result) => {
+                        reset::inject(session, &mut result);
+                        hello::inject(session, &mut result);
+                        JsonRpcResponse::success(req.id, result)
                     }
-                ]
-            })),
-            error: None,
-            id: req.id,
+                    Err(error) => JsonRpcResponse::failure(req.id, error),
+                },
+                None => JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params("prompts/get requires 'name'"),
+                ),
+            }
+        }
+        "resources/list" => match RolePolicy::current(ctx).and_then(|p| resources::list(ctx, &p)) {
+            Ok(result) => JsonRpcResponse::success(req.id, result),
+            Err(e) => JsonRpcResponse::failure(req.id, JsonRpcError::internal(e)),
         },
-        "callTool" | "tools/call" => {
-            if let Some(params) = req.params {
-                let tool_name = params
-                    .get("name")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or_default();
-                let _args = params.get("arguments").cloned().unwrap_or(json!({}));
-
-                match tool_name {
-                    "get_context" => {
-                        let state_manager = handle.state::<crate::state_machine::StateManager>();
-                        let current_role = state_manager.get_state();
-
-                        let db_state = handle.state::<crate::db::DbState>();
-                        let tasks = crate::commands::get_tasks(db_state).unwrap_or_default();
-
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            result: Some(json!({
-                                "content": [
-                                    {
-                                        "type": "text",
-                                        "text": format!("目前角色: {:?}\n當前任務數量: {}", current_role, tasks.len())
-                                    }
-                                ]
-                            })),
-                            error: None,
-                            id: req.id,
-                        }
+        "resources/templates/list" => match RolePolicy::current(ctx) {
+            Ok(policy) => JsonRpcResponse::success(req.id, resources::templates(&policy)),
+            Err(e) => JsonRpcResponse::failure(req.id, JsonRpcError::internal(e)),
+        },
+        "resources/read" => {
+            let uri = req
+                .params
+                .as_ref()
+                .and_then(|p| p.get("uri"))
+                .and_then(|v| v.as_str());
+            match uri {
+                Some(uri) => match RolePolicy::current(ctx)
+                    .map_err(JsonRpcError::internal)
+                    .and_then(|policy| resources::read(ctx, &policy, uri))
+                {
+                    Ok(result) => JsonRpcResponse::success(req.id, result),
+                    Err(error) => JsonRpcResponse::failure(req.id, error),
+                },
+                None => JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params("resources/read requires 'uri'"),
+                ),
+            }
+        }
+        "logging/setLevel" => {
+            let level = req
+                .params
+                .as_ref()
+                .and_then(|p| p.get("level"))
+                .and_then(|v| v.as_str());
+            match level.map(|level| session.set_log_level(level)) {
+                Some(Ok(())) => JsonRpcResponse::success(req.id, json!({})),
+                Some(Err(e)) => JsonRpcResponse::failure(req.id, JsonRpcError::invalid_params(e)),
+                None => JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params("logging/setLevel requires 'level'"),
+                ),
+            }
+        }
+        "resources/subscribe" | "resources/unsubscribe" => {
+            let uri = req
+                .params
+                .as_ref()
+                .and_then(|p| p.get("uri"))
+                .and_then(|v| v.as_str());
+            match uri {
+                Some(uri) => {
+                    if req.method == "resources/subscribe" {
+                        session.subscribe_resource(uri);
+                    } else {
+                        session.unsubscribe_resource(uri);
                     }
-                    "update_mission" => JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": "Task updated (Mock)"
-                                }
-                            ]
-                        })),
-                        error: None,
-                        id: req.id,
-                    },
-                    _ => JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: None,
-                        error: Some(JsonRpcError {
-                            code: -32602,
-                            message: format!("Tool not found: {}", tool_name),
-                            data: None,
-                        }),
-                        id: req.id,
-                    },
-                }
-            } else {
-                JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: "Missing parameters for callTool".to_string(),
-                        data: None,
-                    }),
-                    id: req.id,
+                    JsonRpcResponse::success(req.id, json!({}))
                 }
+                None => JsonRpcResponse::failure(
+                    req.id,
+                    JsonRpcError::invalid_params(format!("{} requires 'uri'", req.method)),
+                ),
             }
         }
-        _ => JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: None,
-            error: Some(JsonRpcError {
-                code: -32601,
-                message: format!("Method not found: {}", req.method),
-                data: None,
-            }),
-            id: req.id,
-        },
+        _ => JsonRpcResponse::failure(req.id, JsonRpcError::method_not_found(&req.method)),
     }
 }
@@ -0,0 +1,193 @@
+//! Role-scoped allowlists for MCP tools and resources. Each role row may carry its
+//! own lists; roles without them (and the Idle state, which has no row) fall back to
+//! the built-in policy below. During an Airlock hold the held role's policy applies.
+
+use crate::commands::RoleData;
+use crate::mcp::resources::{ACTIVITY_URI, BOARD_URI, ROLES_URI, SPEC_URI};
+use crate::mcp::{JsonRpcError, McpContext};
+use crate::state_machine::AppState;
+use serde_json::json;
+
+type Allowlist = Option<&'static [&'static str]>;
+
+const READ_ONLY_TOOLS: &[&str] = &["get_context", "list_tasks", "get_task", "acknowledge_role"];
+
+/// What the active role may see and do. `None` means unrestricted.
+#[derive(Debug, Clone)]
+pub struct RolePolicy {
+    pub role: AppState,
+    pub tools: Option<Vec<String>>,
+    pub resources: Option<Vec<String>>,
+}
+
+impl RolePolicy {
+    /// The policy for the role the desktop app is currently in. While the Airlock holds
+    /// a write, further writes of the held role are queued behind it, not rejected.
+    pub fn current(ctx: &McpContext) -> Result<Self, String> {
+        let role = match ctx.state.get_state() {
+            AppState::Airlock => ctx.state.resume_state().unwrap_or_default(),
+            state => state,
+        };
+        let row = match (role, ctx.state.active_role_id()) {
+            (AppState::Idle | AppState::Airlock, _) | (_, None) => None,
+            (_, Some(id)) => ctx.db.get_roles()?.into_iter().find(|r| r.id == id),
+        };
+        Ok(Self::resolve(role, row.as_ref()))
+    }
+
+    pub fn resolve(role: AppState, row: Option<&RoleData>) -> Self {
+        let (default_tools, default_resources) = builtin(role);
+        Self {
+            role,
+            tools: row
+                .and_then(|r| r.allowed_tools.clone())
+                .or_else(|| default_tools.map(to_strings)),
+            resources: row
+                .and_then(|r| r.allowed_resources.clone())
+                .or_else(|| default_resources.map(to_strings)),
+        }
+    }
+
+    pub fn allows_tool(&self, name: &str) -> bool {
+        self.tools
+            .as_ref()
+            .is_none_or(|tools| tools.iter().any(|t| t == name))
+    }
+
+    pub fn allows_resource(&self, uri: &str) -> bool {
+        self.resources
+            .as_ref()
+            .is_none_or(|patterns| patterns.iter().any(|p| matches(p, uri)))
+    }
+
+    pub fn tool_denied(&self, name: &str) -> JsonRpcError {
+        JsonRpcError {
+            code: crate::mcp::jsonrpc::INVALID_PARAMS,
+            message: format!("Tool '{}' is not available to role {:?}", name, self.role),
+            data: Some(json!({ "tool": name, "role": self.role })),
+        }
+    }
+
+    pub fn resource_denied(&self, uri: &str) -> JsonRpcError {
+        JsonRpcError {
+            code: crate::mcp::jsonrpc::INVALID_PARAMS,
+            message: format!(
+                "Resource '{}' is not available to role {:?}",
+                uri, self.role
+            ),
+            data: Some(json!({ "uri": uri, "role": self.role })),
+        }
+    }
+}
+
+/// Built-in allowlists: Coder works on tasks and workspace files but cannot create or
+/// delete tasks, Reviewer only reads and moves tasks, Architect plans the whole board.
+/// Idle is read-only until a role is chosen; Airlock only applies if no role is held.
+fn builtin(role: AppState) -> (Allowlist, Allowlist) {
+    match role {
+        AppState::Architect => (None, None),
+        AppState::Coder => (
+            Some(&[
+                "get_context",
+                "list_tasks",
+                "get_task",
+                "update_mission",
+                "update_task",
+                "write_file",
+                "switch_role",
+                "acknowledge_role",
+            ]),
+            Some(&[
+                SPEC_URI,
+                BOARD_URI,
+                "taskrails://task/*",
+                "taskrails://role/ai_codegen",
+            ]),
+        ),
+        AppState::Reviewer => (
+            Some(&[
+                "get_context",
+                "list_tasks",
+                "get_task",
+                "update_mission",
+                "switch_role",
+                "acknowledge_role",
+            ]),
+            Some(&[
+                SPEC_URI,
+                BOARD_URI,
+                ACTIVITY_URI,
+                "taskrails://task/*",
+                "taskrails://role/ai_review_bot",
+            ]),
+        ),
+        AppState::Idle | AppState::Airlock => (
+            Some(READ_ONLY_TOOLS),
+            Some(&[SPEC_URI, BOARD_URI, ROLES_URI, "taskrails://task/*"]),
+        ),
+    }
+}
+
+fn to_strings(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+/// Exact match, or prefix match when the pattern ends with `*`.
+fn matches(pattern: &str, uri: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => uri.starts_with(prefix),
+        None => pattern == uri,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::{StateManager, Trigger};
+
+    #[test]
+    fn custom_role_allowlist_blocks_tools() {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-permissions-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = crate::db::open(&dir).unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO roles (id, name, agent_name, role_type, is_default, allowed_tools, base_role)
+                 VALUES ('readonly_coder', 'Readonly', 'Bot', 'ai', 0, '[\"get_context\"]', 'coder')",
+                [],
+            )
+            .unwrap();
+        let ctx = McpContext::headless(db, StateManager::new());
+        let (state, role_id) = AppState::resolve_role(&ctx.db, "readonly_coder").unwrap();
+        assert_eq!(state, AppState::Coder);
+        ctx.state
+            .transition_as(&ctx.db, state, role_id, Trigger::User, None)
+            .unwrap();
+
+        let policy = RolePolicy::current(&ctx).unwrap();
+        assert_eq!(policy.role, AppState::Coder);
+        assert!(policy.allows_tool("get_context"));
+        assert!(!policy.allows_tool("update_task"));
+        // The custom row only overrides tools; resources fall back to Coder's
+        assert!(policy.allows_resource("taskrails://task/TSK-1"));
+        assert!(!policy.allows_resource(ACTIVITY_URI));
+
+        let denied = policy.tool_denied("update_task");
+        assert_eq!(denied.code, crate::mcp::jsonrpc::INVALID_PARAMS);
+        assert_eq!(denied.data.unwrap()["tool"], "update_task");
+
+        // An Airlock hold keeps the held role's policy
+        ctx.state
+            .transition(&ctx.db, AppState::Airlock, Trigger::Airlock, None)
+            .unwrap();
+        assert!(!RolePolicy::current(&ctx)
+            .unwrap()
+            .allows_tool("update_task"));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::commands::RoleData;
+use crate::mcp::{resources, JsonRpcError, McpContext};
+use crate::state_machine::AppState;
+use serde_json::json;
+
+const BUILTIN_STATES: [AppState; 3] = [AppState::Coder, AppState::Reviewer, AppState::Architect];
+
+/// Built-in roles are exposed under their `set_role` names, custom roles under their id.
+pub fn prompt_name(role: &RoleData) -> String {
+    BUILTIN_STATES
+        .iter()
+        .find(|state| state.role_id() == Some(role.id.as_str()))
+        .and_then(|state| state.role_name())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| role.id.clone())
+}
+
+pub fn list(ctx: &McpContext) -> Result<serde_json::Value, String> {
+    let db_state = &ctx.db;
+    let prompts: Vec<_> = db_state
+        .get_roles()?
+        .iter()
+        .filter(|role| role.role_type == "ai")
+        .map(|role| {
+            json!({
+                "name": prompt_name(role),
+                "description": format!("以 {} ({}) 的身分工作", role.agent_name, role.name),
+                "arguments": [
+                    {
+                        "name": "task_id",
+                        "description": "要處理的任務 ID，會附上任務標題與描述",
+                        "required": false
+                    },
+                    {
+                        "name": "include_spec",
+                        "description": "是否附上專案規格 (預設 true)",
+                        "required": false
+                    }
+                ]
+            })
+        })
+        .collect();
+
+    Ok(json!({ "prompts": prompts }))
+}
+
+pub fn get(
+    ctx: &McpContext,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let db_state = &ctx.db;
+
+    let role = db_state
+        .get_roles()
+        .map_err(JsonRpcError::internal)?
+        .into_iter()
+        .find(|role| prompt_name(role) == name || role.id == name)
+        .ok_or_else(|| JsonRpcError::invalid_params(format!("Prompt not found: {}", name)))?;
+
+    let mut text = format!("# 角色: {} ({})\n\n", role.agent_name, role.name);
+    if let Some(system_prompt) = role.system_prompt.as_deref() {
+        text.push_str(&format!("{}\n\n", system_prompt));
+    }
+
+    let include_spec = args
+        .get("include_spec")
+        .and_then(|v| v.as_str())
+        .map(|v| v != "false")
+        .unwrap_or(true);
+    if include_spec {
+        let spec = db_state
+            .get_project_spec()
+            .map_err(JsonRpcError::internal)?;
+        if let Some(spec) = spec {
+            text.push_str("## 專案規格\n\n");
+            // Demote the spec's headings so it nests under this section.
+            for line in resources::spec_markdown(Some(&spec)).lines() {
+                if line.starts_with('#') {
+                    text.push_str("##");
+                }
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+    }
+
+    if let Some(task_id) = args.get("task_id").and_then(|v| v.as_str()) {
+        let task = db_state
+            .get_task(task_id)
+            .map_err(JsonRpcError::internal)?
+            .ok_or_else(|| JsonRpcError::invalid_params(format!("Task not found: {}", task_id)))?;
+
+        text.push_str(&format!("## 指派任務\n\n### {}: {}\n", task.id, task.title));
+        text.push_str(&format!(
+            "- **Status**: {}\n- **Phase**: {}\n- **Priority**: P{}\n",
+            task.status,
+            task.phase.as_deref().unwrap_or("PHASE 1"),
+            task.priority.as_deref().unwrap_or("3")
+        ));
+        if let Some(description) = task.description.as_deref().filter(|d| !d.is_empty()) {
+            text.push_str(&format!("\n{}\n", description));
+        }
+    }
+
+    Ok(json!({
+        "description": format!("{} ({})", role.agent_name, role.name),
+        "messages": [{
+            "role": "user",
+            "content": { "type": "text", "text": text }
+        }]
+    }))
+}
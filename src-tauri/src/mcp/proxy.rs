@@ -0,0 +1,279 @@
+//! Forwards a `mcp-stdio` client to an already running desktop instance, so the agent
+//! and the UI share one database connection, one role and one session list.
+
+use crate::mcp::sse::MCP_PORT;
+use crate::mcp::stdio::{RequestPool, MAX_CONCURRENT_REQUESTS, MAX_PENDING_REQUESTS};
+use crate::utils::sse::SseParser;
+use futures::stream::StreamExt;
+use reqwest::{header, StatusCode};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const ACCEPT: &str = "application/json, text/event-stream";
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+pub struct DesktopProxy {
+    client: reqwest::Client,
+    endpoint: String,
+    session_id: Arc<Mutex<Option<String>>>,
+}
+
+impl DesktopProxy {
+    /// Probes the desktop's local MCP server. `None` means no TaskRails instance is
+    /// listening and the caller should serve stdio standalone.
+    pub async fn connect() -> Option<Self> {
+        let base = format!("http://127.0.0.1:{}", MCP_PORT);
+        let client = reqwest::Client::new();
+
+        let health: serde_json::Value = client
+            .get(format!("{}/health", base))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+        if health.get("name").and_then(|v| v.as_str()) != Some("TaskRails") {
+            return None;
+        }
+
+        Some(Self {
+            client,
+            endpoint: format!("{}/mcp", base),
+            session_id: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Relays stdin lines to the desktop until EOF. All stdout writes go through one
+    /// writer task so responses and notifications never interleave mid-line.
+    pub async fn run(self) {
+        let proxy = Arc::new(self);
+        proxy.relay_stdio().await;
+    }
+
+    async fn relay_stdio(self: &Arc<Self>) {
+        let pool = RequestPool::new(MAX_CONCURRENT_REQUESTS, MAX_PENDING_REQUESTS);
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let writer = tokio::spawn(async move {
+            let mut stdout = stdout();
+            while let Some(message) = rx.recv().await {
+                if let Err(e) = stdout.write_all(format!("{}\n", message).as_bytes()).await {
+                    eprintln!("Failed to write to stdout: {}", e);
+                    break;
+                }
+                let _ = stdout.flush().await;
+            }
+        });
+
+        let mut reader = BufReader::new(stdin());
+        let mut line = String::new();
+        let mut listener: Option<tokio::task::JoinHandle<()>> = None;
+
+        loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) => break, // EOF
+                Ok(_) => {
+                    let trimmed = line.trim();
+                    if trimmed.is_empty() {
+                        continue;
+                    }
+                    let message: Option<serde_json::Value> = serde_json::from_str(trimmed).ok();
+                    let method = message
+                        .as_ref()
+                        .and_then(|m| m.get("method"))
+                        .and_then(|m| m.as_str());
+                    if method == Some("notifications/cancelled") {
+                        // Dropping the HTTP request also drops the handler on the desktop side.
+                        pool.cancel(message.as_ref().and_then(|m| m.get("params")));
+                        continue;
+                    }
+
+                    // Until the desktop has assigned a session, requests go one at a time.
+                    if self.session_id.lock().unwrap().is_none() {
+                        self.relay(trimmed.to_string(), &tx).await;
+                    } else {
+                        let id = message.as_ref().and_then(|m| m.get("id")).cloned();
+                        let proxy = self.clone();
+                        let body = trimmed.to_string();
+                        let tx = tx.clone();
+                        pool.spawn(id.as_ref(), async move {
+                            proxy.relay(body, &tx).await;
+                        })
+                        .await;
+                    }
+
+                    if listener.is_none() && self.session_id.lock().unwrap().is_some() {
+                        listener = Some(tokio::spawn(self.listen(tx.clone())));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading from stdin: {}", e);
+                    break;
+                }
+            }
+        }
+
+        pool.finish().await;
+        if let Some(listener) = listener {
+            listener.abort();
+        }
+        self.close().await;
+        drop(tx);
+        let _ = writer.await;
+    }
+
+    async fn relay(&self, body: String, tx: &mpsc::UnboundedSender<String>) {
+        if let Err(e) = self.forward(&body, tx).await {
+            eprintln!("Failed to forward MCP request: {}", e);
+            if let Some(id) = request_id(&body) {
+                let _ = tx.send(error_response(id, &e));
+            }
+        }
+    }
+
+    async fn forward(&self, body: &str, tx: &mpsc::UnboundedSender<String>) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&self.endpoint)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, ACCEPT)
+            .body(body.to_string());
+        if let Some(id) = self.session_id.lock().unwrap().clone() {
+            request = request.header(SESSION_HEADER, id);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("TaskRails desktop app is not reachable: {}", e))?;
+        let status = response.status();
+        if status == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            // JSON-RPC errors (e.g. -32700 for a malformed line) are passed through as is.
+            if serde_json::from_str::<serde_json::Value>(&text)
+                .is_ok_and(|v| v.get("jsonrpc").is_some())
+            {
+                let _ = tx.send(text);
+                return Ok(());
+            }
+            return Err(format!("Desktop MCP server returned {}: {}", status, text));
+        }
+
+        if let Some(id) = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+        {
+            *self.session_id.lock().unwrap() = Some(id.to_string());
+        }
+
+        if is_event_stream(&response) {
+            let mut parser = SseParser::new();
+            let mut body = response.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(|e| e.to_string())?;
+                for event in parser.push(&chunk) {
+                    let _ = tx.send(event.data);
+                }
+            }
+        } else {
+            let text = response.text().await.map_err(|e| e.to_string())?;
+            let _ = tx.send(text.trim().to_string());
+        }
+        Ok(())
+    }
+
+    /// Keeps the session's server-to-client stream open, resuming from the last seen
+    /// event after a dropped connection. Ends once the desktop forgets the session.
+    fn listen(&self, tx: mpsc::UnboundedSender<String>) -> impl std::future::Future<Output = ()> {
+        let client = self.client.clone();
+        let endpoint = self.endpoint.clone();
+        let session_id = self.session_id.lock().unwrap().clone().unwrap_or_default();
+
+        async move {
+            let mut last_event_id: Option<String> = None;
+            loop {
+                let mut request = client
+                    .get(&endpoint)
+                    .header(header::ACCEPT, "text/event-stream")
+                    .header(SESSION_HEADER, &session_id);
+                if let Some(id) = &last_event_id {
+                    request = request.header("last-event-id", id);
+                }
+
+                match request.send().await {
+                    Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+                        eprintln!("Desktop closed MCP session {}", session_id);
+                        return;
+                    }
+                    Ok(response) if response.status().is_success() => {
+                        let mut parser = SseParser::new();
+                        let mut body = response.bytes_stream();
+                        while let Some(Ok(chunk)) = body.next().await {
+                            for event in parser.push(&chunk) {
+                                if event.id.is_some() {
+                                    last_event_id = event.id;
+                                }
+                                let _ = tx.send(event.data);
+                            }
+                        }
+                    }
+                    Ok(response) => {
+                        eprintln!("Notification stream rejected: {}", response.status());
+                    }
+                    Err(e) => eprintln!("Notification stream error: {}", e),
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+
+    async fn close(&self) {
+        let Some(id) = self.session_id.lock().unwrap().take() else {
+            return;
+        };
+        let _ = self
+            .client
+            .delete(&self.endpoint)
+            .header(SESSION_HEADER, id)
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await;
+    }
+}
+
+fn is_event_stream(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("text/event-stream"))
+        .unwrap_or(false)
+}
+
+fn request_id(body: &str) -> Option<serde_json::Value> {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()?
+        .get("id")
+        .filter(|id| !id.is_null())
+        .cloned()
+}
+
+fn error_response(id: serde_json::Value, message: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": -32603, "message": message }
+    })
+    .to_string()
+}
@@ -0,0 +1,257 @@
+use crate::commands::{SpecData, TaskData};
+use crate::mcp::permissions::RolePolicy;
+use crate::mcp::{JsonRpcError, McpContext};
+use serde_json::json;
+
+pub const SPEC_URI: &str = "taskrails://spec";
+pub const BOARD_URI: &str = "taskrails://board";
+pub const ROLES_URI: &str = "taskrails://roles";
+pub const ACTIVITY_URI: &str = "taskrails://activity";
+const TASK_PREFIX: &str = "taskrails://task/";
+const ROLE_PREFIX: &str = "taskrails://role/";
+
+/// JSON-RPC error code MCP uses for unknown resource URIs.
+const RESOURCE_NOT_FOUND: i32 = -32002;
+
+pub fn task_uri(id: &str) -> String {
+    format!("{}{}", TASK_PREFIX, id)
+}
+
+pub fn role_uri(id: &str) -> String {
+    format!("{}{}", ROLE_PREFIX, id)
+}
+
+pub fn list(ctx: &McpContext, policy: &RolePolicy) -> Result<serde_json::Value, String> {
+    let db_state = &ctx.db;
+
+    let mut resources = vec![
+        json!({
+            "uri": SPEC_URI,
+            "name": "Project Spec",
+            "description": "專案規格書 (概述、技術棧、資料結構、功能、設計、規則)",
+            "mimeType": "text/markdown"
+        }),
+        json!({
+            "uri": BOARD_URI,
+            "name": "Mission Board",
+            "description": "依狀態分組的完整任務看板",
+            "mimeType": "text/markdown"
+        }),
+        json!({
+            "uri": ROLES_URI,
+            "name": "Role Registry",
+            "description": "所有角色與其系統指令",
+            "mimeType": "application/json"
+        }),
+        json!({
+            "uri": ACTIVITY_URI,
+            "name": "Activity Log",
+            "description": "最近 100 筆系統活動紀錄",
+            "mimeType": "application/json"
+        }),
+    ];
+
+    for role in db_state.get_roles()? {
+        resources.push(json!({
+            "uri": role_uri(&role.id),
+            "name": format!("Role: {} ({})", role.agent_name, role.name),
+            "mimeType": "text/markdown"
+        }));
+    }
+
+    for task in db_state.get_tasks()? {
+        resources.push(json!({
+            "uri": task_uri(&task.id),
+            "name": format!("{}: {}", task.id, task.title),
+            "description": format!("[{}] {}", task.status, task.phase.as_deref().unwrap_or("-")),
+            "mimeType": "application/json"
+        }));
+    }
+
+    resources.retain(|r| {
+        r.get("uri")
+            .and_then(|u| u.as_str())
+            .is_some_and(|uri| policy.allows_resource(uri))
+    });
+    Ok(json!({ "resources": resources }))
+}
+
+pub fn templates(policy: &RolePolicy) -> serde_json::Value {
+    let templates = [
+        (
+            TASK_PREFIX,
+            json!({
+                "uriTemplate": format!("{}{{id}}", TASK_PREFIX),
+                "name": "Task",
+                "description": "單一任務的完整欄位與留言",
+                "mimeType": "application/json"
+            }),
+        ),
+        (
+            ROLE_PREFIX,
+            json!({
+                "uriTemplate": format!("{}{{id}}", ROLE_PREFIX),
+                "name": "Role Prompt",
+                "description": "單一角色的系統指令",
+                "mimeType": "text/markdown"
+            }),
+        ),
+    ];
+
+    // A template is only offered if the role may read any URI it expands to.
+    let templates: Vec<_> = templates
+        .into_iter()
+        .filter(|(prefix, _)| policy.allows_resource(&format!("{}*", prefix)))
+        .map(|(_, template)| template)
+        .collect();
+    json!({ "resourceTemplates": templates })
+}
+
+pub fn read(
+    ctx: &McpContext,
+    policy: &RolePolicy,
+    uri: &str,
+) -> Result<serde_json::Value, JsonRpcError> {
+    if !policy.allows_resource(uri) {
+        return Err(policy.resource_denied(uri));
+    }
+    let db_state = &ctx.db;
+
+    let (mime_type, text) = match uri {
+        SPEC_URI => {
+            let spec = db_state
+                .get_project_spec()
+                .map_err(JsonRpcError::internal)?;
+            ("text/markdown", spec_markdown(spec.as_ref()))
+        }
+        BOARD_URI => {
+            let tasks = db_state.get_tasks().map_err(JsonRpcError::internal)?;
+            ("text/markdown", board_markdown(&tasks))
+        }
+        ROLES_URI => {
+            let roles = db_state.get_roles().map_err(JsonRpcError::internal)?;
+            ("application/json", to_json_text(&roles))
+        }
+        ACTIVITY_URI => {
+            let activity = db_state.get_activity().map_err(JsonRpcError::internal)?;
+            ("application/json", to_json_text(&activity))
+        }
+        _ => {
+            if let Some(id) = uri.strip_prefix(TASK_PREFIX) {
+                let task = db_state
+                    .get_task(id)
+                    .map_err(JsonRpcError::internal)?
+                    .ok_or_else(|| not_found(uri))?;
+                let comments = db_state
+                    .get_task_comments(id)
+                    .map_err(JsonRpcError::internal)?;
+                (
+                    "application/json",
+                    to_json_text(&json!({ "task": task, "comments": comments })),
+                )
+            } else if let Some(id) = uri.strip_prefix(ROLE_PREFIX) {
+                let role = db_state
+                    .get_roles()
+                    .map_err(JsonRpcError::internal)?
+                    .into_iter()
+                    .find(|r| r.id == id)
+                    .ok_or_else(|| not_found(uri))?;
+                (
+                    "text/markdown",
+                    format!(
+                        "# {} ({})\n\n{}\n",
+                        role.agent_name,
+                        role.name,
+                        role.system_prompt.unwrap_or_default()
+                    ),
+                )
+            } else {
+                return Err(not_found(uri));
+            }
+        }
+    };
+
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": mime_type,
+            "text": text
+        }]
+    }))
+}
+
+fn not_found(uri: &str) -> JsonRpcError {
+    JsonRpcError {
+        code: RESOURCE_NOT_FOUND,
+        message: format!("Resource not found: {}", uri),
+        data: Some(json!({ "uri": uri })),
+    }
+}
+
+fn to_json_text<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+pub fn spec_markdown(spec: Option<&SpecData>) -> String {
+    let Some(spec) = spec else {
+        return "# Project Spec\n\n(尚未建立專案規格)\n".to_string();
+    };
+
+    let mut md = format!("# {}\n\n", spec.name.as_deref().unwrap_or("Project Spec"));
+    for (label, value) in [
+        ("Overview", &spec.overview),
+        ("Tech Stack", &spec.tech_stack),
+        ("Data Structure", &spec.data_structure),
+        ("Features", &spec.features),
+        ("Design", &spec.design),
+        ("Rules", &spec.rules),
+    ] {
+        if let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) {
+            md.push_str(&format!("## {}\n\n{}\n\n", label, value));
+        }
+    }
+    md
+}
+
+/// Same layout as the MISSION BOARD section of the frontend's `.taskrails` export.
+pub fn board_markdown(tasks: &[TaskData]) -> String {
+    let mut md = "# MISSION BOARD\n\n".to_string();
+
+    for (status, label) in [
+        ("todo", "待辦事項 / TODO"),
+        ("doing", "進行中 / IN PROGRESS"),
+        ("done", "已完成 / COMPLETED"),
+    ] {
+        let column: Vec<_> = tasks.iter().filter(|t| t.status == status).collect();
+        if column.is_empty() {
+            continue;
+        }
+
+        md.push_str(&format!("### [STATUS: {}]\n\n", label));
+        for task in column {
+            md.push_str(&format!("#### {}: {}\n", task.id, task.title));
+            md.push_str(&format!(
+                "- **Phase**: {}\n",
+                task.phase.as_deref().unwrap_or("PHASE 1")
+            ));
+            md.push_str(&format!(
+                "- **Priority**: P{}\n",
+                task.priority.as_deref().unwrap_or("3")
+            ));
+            if let Some(tag) = &task.tag {
+                md.push_str(&format!("- **Tag**: {}\n", tag));
+            }
+            if let Some(assignee) = &task.assignee {
+                md.push_str(&format!("- **Assignee**: {}\n", assignee));
+            }
+            if task.is_reworked == Some(true) {
+                md.push_str("- **Flags**: [REWORKED]\n");
+            }
+            if let Some(description) = task.description.as_deref().filter(|d| !d.is_empty()) {
+                md.push_str(&format!("\n{}\n", description));
+            }
+            md.push_str("\n---\n\n");
+        }
+    }
+    md
+}
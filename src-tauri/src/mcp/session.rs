@@ -0,0 +1,534 @@
+use crate::mcp::hello::PendingHello;
+use crate::mcp::jsonrpc::JsonRpcResponse;
+use crate::mcp::reset::PendingReset;
+use crate::mcp::token_monitor::TokenUsage;
+use crate::state_machine::AppState;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Messages kept per session so a reconnecting client can resume with `Last-Event-ID`.
+const HISTORY_LIMIT: usize = 256;
+
+/// MCP log levels (RFC 5424 severities), least severe first.
+pub const LOG_LEVELS: [&str; 8] = [
+    "debug",
+    "info",
+    "notice",
+    "warning",
+    "error",
+    "critical",
+    "alert",
+    "emergency",
+];
+/// Until the client calls `logging/setLevel`, messages from `info` up are sent.
+const DEFAULT_LOG_LEVEL: usize = 1;
+
+/// Stream id of the session's long-lived server-to-client stream.
+pub const STANDALONE_STREAM: u64 = 0;
+
+/// A message queued for a client, numbered so streams can be resumed.
+/// Ids are unique per session; `stream` tells which response stream carried it.
+#[derive(Debug, Clone)]
+pub struct SessionEvent {
+    pub id: u64,
+    pub stream: u64,
+    pub data: String,
+}
+
+/// The SSE stream answering one Streamable HTTP request. While the request runs inside
+/// [`ResponseStream::scope`], everything sent to its session goes out on this stream.
+#[derive(Clone)]
+pub struct ResponseStream {
+    session_id: String,
+    stream: u64,
+    tx: mpsc::UnboundedSender<SessionEvent>,
+}
+
+tokio::task_local! {
+    static RESPONSE_STREAM: ResponseStream;
+}
+
+impl ResponseStream {
+    /// Runs `future` with the session's messages routed to this stream. The receiver
+    /// ends once the future has finished.
+    pub async fn scope<F: std::future::Future>(self, future: F) -> F::Output {
+        RESPONSE_STREAM.scope(self, future).await
+    }
+}
+
+/// What the client declared in `initialize`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub name: String,
+    pub version: Option<String>,
+    pub protocol_version: String,
+    pub capabilities: serde_json::Value,
+}
+
+impl ClientInfo {
+    /// Top-level capability names, e.g. `sampling`, `roots`, `elicitation`.
+    pub fn capability_names(&self) -> Vec<String> {
+        self.capabilities
+            .as_object()
+            .map(|caps| caps.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Client capability a server-initiated request depends on.
+fn required_capability(method: &str) -> Option<&'static str> {
+    match method.split('/').next() {
+        Some("sampling") => Some("sampling"),
+        Some("roots") => Some("roots"),
+        Some("elicitation") => Some("elicitation"),
+        _ => None,
+    }
+}
+
+type PendingReply = oneshot::Sender<Result<serde_json::Value, String>>;
+
+/// One connected MCP client. Messages sent to it are delivered over its own stream.
+pub struct Session {
+    pub id: String,
+    pub transport: &'static str,
+    pub connected_at: chrono::DateTime<chrono::Utc>,
+    pub tokens: TokenUsage,
+    role: Mutex<AppState>,
+    subscriptions: Mutex<HashSet<String>>,
+    client: Mutex<Option<ClientInfo>>,
+    roots: Mutex<Vec<serde_json::Value>>,
+    pending_reset: Mutex<Option<PendingReset>>,
+    pending_hello: Mutex<Option<PendingHello>>,
+    pending: Mutex<HashMap<String, PendingReply>>,
+    next_request_id: AtomicU64,
+    events: broadcast::Sender<SessionEvent>,
+    history: Mutex<VecDeque<SessionEvent>>,
+    next_event_id: AtomicU64,
+    next_stream_id: AtomicU64,
+    log_level: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub transport: String,
+    pub role: AppState,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub connected_at: String,
+    pub client: Option<ClientInfo>,
+    pub roots: Vec<serde_json::Value>,
+    /// Role the client has been asked to acknowledge (Hello Protocol).
+    pub awaiting_ack: Option<AppState>,
+    /// Set when the client kept acting under this old role after a switch.
+    pub stale_role: Option<AppState>,
+}
+
+impl Session {
+    /// Opens a response stream for a single request.
+    pub fn open_stream(&self) -> (ResponseStream, mpsc::UnboundedReceiver<SessionEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stream = ResponseStream {
+            session_id: self.id.clone(),
+            stream: self.next_stream_id.fetch_add(1, Ordering::Relaxed) + 1,
+            tx,
+        };
+        (stream, rx)
+    }
+
+    /// Numbers `data` and keeps it for replay without delivering it to live streams.
+    pub fn record(&self, stream: u64, data: String) -> SessionEvent {
+        let event = SessionEvent {
+            id: self.next_event_id.fetch_add(1, Ordering::Relaxed) + 1,
+            stream,
+            data,
+        };
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+        event
+    }
+
+    /// Queues a JSON-RPC message for this client: on the response stream of the request
+    /// being handled, if any, else on the standalone stream. Returns false if no stream
+    /// is listening, in which case the message is only available through replay.
+    pub fn send(&self, data: String) -> bool {
+        let response_stream = RESPONSE_STREAM
+            .try_with(|s| (s.session_id == self.id).then(|| s.clone()))
+            .ok()
+            .flatten();
+        match response_stream {
+            Some(stream) => {
+                let event = self.record(stream.stream, data);
+                stream.tx.send(event).is_ok()
+            }
+            None => {
+                let event = self.record(STANDALONE_STREAM, data);
+                self.events.send(event).is_ok()
+            }
+        }
+    }
+
+    /// Sends a server-initiated JSON-RPC notification to this client.
+    pub fn notify(&self, method: &str, params: serde_json::Value) -> bool {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        });
+        self.send(message.to_string())
+    }
+
+    /// Sets the minimum level of `notifications/message` sent (`logging/setLevel`).
+    pub fn set_log_level(&self, level: &str) -> Result<(), String> {
+        let index = LOG_LEVELS
+            .iter()
+            .position(|l| *l == level)
+            .ok_or_else(|| format!("Unknown log level: {}", level))?;
+        self.log_level.store(index, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sends a `notifications/message` log entry unless it is below the client's level.
+    pub fn log(&self, level: &str, logger: &str, data: serde_json::Value) -> bool {
+        let index = LOG_LEVELS.iter().position(|l| *l == level).unwrap_or(0);
+        if index < self.log_level.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.notify(
+            "notifications/message",
+            serde_json::json!({ "level": level, "logger": logger, "data": data }),
+        )
+    }
+
+    /// Resource URIs this client asked to hear about via `resources/subscribe`.
+    pub fn subscribe_resource(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().insert(uri.to_string());
+    }
+
+    pub fn unsubscribe_resource(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().remove(uri);
+    }
+
+    pub fn is_subscribed(&self, uri: &str) -> bool {
+        self.subscriptions.lock().unwrap().contains(uri)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Events recorded after `last_event_id` on the same stream as that event, oldest
+    /// first, together with that stream's id. Unknown ids resume the standalone stream.
+    pub fn replay_after(&self, last_event_id: u64) -> (u64, Vec<SessionEvent>) {
+        let history = self.history.lock().unwrap();
+        let stream = history
+            .iter()
+            .find(|e| e.id == last_event_id)
+            .map(|e| e.stream)
+            .unwrap_or(STANDALONE_STREAM);
+        let events = history
+            .iter()
+            .filter(|e| e.id > last_event_id && e.stream == stream)
+            .cloned()
+            .collect();
+        (stream, events)
+    }
+
+    pub fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_active.lock().unwrap().elapsed()
+    }
+
+    pub fn has_listeners(&self) -> bool {
+        self.events.receiver_count() > 0
+    }
+
+    /// The role this client was last told about.
+    pub fn role(&self) -> AppState {
+        *self.role.lock().unwrap()
+    }
+
+    pub fn set_role(&self, role: AppState) {
+        *self.role.lock().unwrap() = role;
+    }
+
+    pub fn client(&self) -> Option<ClientInfo> {
+        self.client.lock().unwrap().clone()
+    }
+
+    pub fn set_client(&self, client: ClientInfo) {
+        *self.client.lock().unwrap() = Some(client);
+    }
+
+    /// Whether the client declared `capability` during initialization.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.client
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|c| c.capabilities.get(capability).is_some())
+    }
+
+    pub fn set_roots(&self, roots: Vec<serde_json::Value>) {
+        *self.roots.lock().unwrap() = roots;
+    }
+
+    /// Replaces any undelivered reset; only the latest role switch matters.
+    pub fn set_pending_reset(&self, reset: PendingReset) {
+        *self.pending_reset.lock().unwrap() = Some(reset);
+    }
+
+    pub fn take_pending_reset(&self) -> Option<PendingReset> {
+        self.pending_reset.lock().unwrap().take()
+    }
+
+    /// The Hello Protocol handshake this session still owes, if any.
+    pub fn pending_hello(&self) -> Option<PendingHello> {
+        self.pending_hello.lock().unwrap().clone()
+    }
+
+    pub fn set_pending_hello(&self, hello: PendingHello) {
+        *self.pending_hello.lock().unwrap() = Some(hello);
+    }
+
+    pub fn clear_pending_hello(&self) {
+        *self.pending_hello.lock().unwrap() = None;
+    }
+
+    /// Sends a server-initiated request and waits for the client's reply. Refused
+    /// up front when the method needs a capability the client did not declare.
+    pub async fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, String> {
+        if let Some(capability) = required_capability(method) {
+            if !self.supports(capability) {
+                return Err(format!(
+                    "Client did not declare the '{}' capability required for {}",
+                    capability, method
+                ));
+            }
+        }
+
+        let id = format!(
+            "taskrails-{}",
+            self.next_request_id.fetch_add(1, Ordering::Relaxed) + 1
+        );
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), tx);
+
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+        self.send(message.to_string());
+
+        let reply = tokio::time::timeout(timeout, rx).await;
+        self.pending.lock().unwrap().remove(&id);
+        match reply {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(format!("{} was abandoned", method)),
+            Err(_) => Err(format!("Client did not answer {} in time", method)),
+        }
+    }
+
+    /// Routes a client's reply to the `request` waiting for it. Unknown ids are ignored.
+    pub fn resolve(&self, response: JsonRpcResponse) {
+        let Some(id) = response.id.as_ref().and_then(|id| id.as_str()) else {
+            return;
+        };
+        let Some(waiter) = self.pending.lock().unwrap().remove(id) else {
+            return;
+        };
+        let result = match (response.result, response.error) {
+            (_, Some(error)) => Err(format!("{} ({})", error.message, error.code)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(serde_json::Value::Null),
+        };
+        let _ = waiter.send(result);
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        let (input_tokens, output_tokens) = self.tokens.get_usage();
+        SessionInfo {
+            id: self.id.clone(),
+            transport: self.transport.to_string(),
+            role: self.role(),
+            input_tokens,
+            output_tokens,
+            connected_at: self.connected_at.to_rfc3339(),
+            client: self.client(),
+            roots: self.roots.lock().unwrap().clone(),
+            awaiting_ack: self.pending_hello().map(|h| h.to),
+            stale_role: self.pending_hello().filter(|h| h.flagged).map(|h| h.from),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create(&self, transport: &'static str, role: AppState) -> Arc<Session> {
+        let (events, _) = broadcast::channel(HISTORY_LIMIT);
+        let now = chrono::Utc::now();
+        // The id is the only credential of a Streamable HTTP session, so it must not
+        // be guessable.
+        let id = uuid::Uuid::new_v4().to_string();
+
+        let session = Arc::new(Session {
+            id: id.clone(),
+            transport,
+            connected_at: now,
+            tokens: TokenUsage::default(),
+            role: Mutex::new(role),
+            subscriptions: Mutex::new(HashSet::new()),
+            client: Mutex::new(None),
+            roots: Mutex::new(Vec::new()),
+            pending_reset: Mutex::new(None),
+            pending_hello: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+            next_request_id: AtomicU64::new(0),
+            events,
+            history: Mutex::new(VecDeque::new()),
+            next_event_id: AtomicU64::new(0),
+            next_stream_id: AtomicU64::new(STANDALONE_STREAM),
+            log_level: AtomicUsize::new(DEFAULT_LOG_LEVEL),
+            last_active: Mutex::new(Instant::now()),
+        });
+        self.sessions.lock().unwrap().insert(id, session.clone());
+        session
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn remove(&self, id: &str) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().remove(id)
+    }
+
+    /// Drops sessions of `transport` with no open stream and no traffic for `max_idle`.
+    /// Used for transports where the client may vanish without closing anything.
+    pub fn prune_idle(&self, transport: &str, max_idle: Duration) {
+        self.sessions.lock().unwrap().retain(|_, s| {
+            s.transport != transport || s.has_listeners() || s.idle_for() < max_idle
+        });
+    }
+
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.info())
+            .collect();
+        sessions.sort_by(|a, b| a.connected_at.cmp(&b.connected_at));
+        sessions
+    }
+
+    pub fn all(&self) -> Vec<Arc<Session>> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Sends a parameterless notification such as `notifications/tools/list_changed` to everyone.
+    pub fn notify_all(&self, method: &str) {
+        for session in self.all() {
+            session.notify(method, serde_json::json!({}));
+        }
+    }
+
+    /// Records the new role on every session and, for actual roles, broadcasts
+    /// `notifications/identityChange`. Tools and resources are scoped per role, so
+    /// clients are also told to re-list them.
+    pub fn broadcast_role(&self, role: AppState) {
+        for session in self.all() {
+            session.set_role(role);
+            if let Some(role_name) = role.role_name() {
+                session.notify(
+                    "notifications/identityChange",
+                    serde_json::json!({ "role": role_name }),
+                );
+            }
+            session.notify("notifications/tools/list_changed", serde_json::json!({}));
+            session.notify(
+                "notifications/resources/list_changed",
+                serde_json::json!({}),
+            );
+        }
+    }
+
+    /// Sends `notifications/resources/updated` to every session subscribed to `uri`.
+    pub fn notify_resource_updated(&self, uri: &str) {
+        for session in self.all().into_iter().filter(|s| s.is_subscribed(uri)) {
+            session.notify(
+                "notifications/resources/updated",
+                serde_json::json!({ "uri": uri }),
+            );
+        }
+    }
+}
+
+/// Removes its session from the manager when dropped, i.e. when the client's stream closes.
+pub struct SessionGuard {
+    pub manager: Arc<SessionManager>,
+    pub id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if self.manager.remove(&self.id).is_some() {
+            println!("MCP session {} disconnected", self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_log_level_filters_messages() {
+        let sessions = SessionManager::new();
+        let session = sessions.create("test", AppState::Idle);
+        let mut listener = session.subscribe();
+
+        assert!(!session.log("debug", "test", serde_json::json!("dropped")));
+        assert!(session.log("info", "test", serde_json::json!("sent")));
+
+        session.set_log_level("error").unwrap();
+        assert!(!session.log("warning", "test", serde_json::json!("dropped")));
+        assert!(session.log("error", "test", serde_json::json!("sent")));
+        assert!(session.set_log_level("loud").is_err());
+
+        let mut levels = Vec::new();
+        while let Ok(event) = listener.try_recv() {
+            let message: serde_json::Value = serde_json::from_str(&event.data).unwrap();
+            assert_eq!(message["method"], "notifications/message");
+            levels.push(message["params"]["level"].clone());
+        }
+        assert_eq!(levels, ["info", "error"]);
+    }
+}
@@ -1,66 +1,126 @@
-use crate::mcp::JsonRpcRequest;
+use crate::mcp::session::SessionGuard;
+use crate::mcp::streamable_http;
+use crate::mcp::{jsonrpc, McpContext};
 use axum::{
+    extract::{Query, State},
+    http::StatusCode,
     response::sse::{Event, Sse},
     routing::{get, post},
-    Json, Router,
+    Router,
 };
 use futures::stream::Stream;
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use serde::Deserialize;
+use std::{convert::Infallible, net::SocketAddr};
 use tauri::Manager;
-use tokio::sync::broadcast;
 
 #[derive(Clone)]
 pub struct ServerState {
-    pub handle: tauri::AppHandle,
-    pub tx: Arc<broadcast::Sender<String>>,
+    pub ctx: McpContext,
 }
 
+#[derive(Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
+/// Port of the local MCP HTTP server (SSE, Streamable HTTP and health check).
+pub const MCP_PORT: u16 = 4567;
+
 pub async fn start_sse_server(handle: tauri::AppHandle) {
-    let (tx, _rx) = broadcast::channel(100);
-    let tx = Arc::new(tx);
+    let ctx = McpContext::from_app(&handle);
 
     // Manage state for other parts of the app
-    handle.manage(ServerState {
-        handle: handle.clone(),
-        tx: tx.clone(),
-    });
+    handle.manage(ServerState { ctx: ctx.clone() });
+    tokio::spawn(ctx.airlock.clone().watch(ctx.clone()));
+    tokio::spawn(ctx.tokens.clone().persist(ctx.db.clone()));
 
-    let state = ServerState { handle, tx };
+    let state = ServerState { ctx };
 
     let app = Router::new()
+        .route("/health", get(health_handler))
         .route("/sse", get(sse_handler))
         .route("/messages", post(message_handler))
+        .route(
+            "/mcp",
+            post(streamable_http::post_handler)
+                .get(streamable_http::get_handler)
+                .delete(streamable_http::delete_handler),
+        )
         .with_state(state);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 4567));
-    println!("MCP SSE Server listening on {}", addr);
+    let addr = SocketAddr::from(([127, 0, 0, 1], MCP_PORT));
+    println!("MCP SSE Server listening on {} (/sse, /mcp)", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Lets a `mcp-stdio` process detect a running desktop instance it can forward to.
+async fn health_handler() -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({
+        "name": "TaskRails",
+        "version": env!("CARGO_PKG_VERSION"),
+        "pid": std::process::id()
+    }))
+}
+
+/// Opens a session: the first event tells the client where to POST its requests,
+/// every later event carries a response or notification for this client only.
 async fn sse_handler(
-    axum::extract::State(state): axum::extract::State<ServerState>,
+    State(state): State<ServerState>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     use futures::stream::StreamExt;
     use tokio_stream::wrappers::BroadcastStream;
 
-    let rx = state.tx.subscribe();
-    let stream = BroadcastStream::new(rx).map(|msg| match msg {
-        Ok(msg) => Ok(Event::default().data(msg)),
-        Err(_) => Ok(Event::default().comment("missed message")),
+    let role = state.ctx.state.get_state();
+    let session = state.ctx.sessions.create("sse", role);
+    let rx = session.subscribe();
+    println!("MCP SSE session {} connected", session.id);
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/messages?sessionId={}", session.id));
+
+    // The guard lives inside the stream, so the session is dropped with the connection.
+    let guard = SessionGuard {
+        manager: state.ctx.sessions.clone(),
+        id: session.id.clone(),
+    };
+    let messages = BroadcastStream::new(rx).map(move |msg| {
+        let _guard = &guard;
+        match msg {
+            Ok(event) => Ok(Event::default().event("message").data(event.data)),
+            Err(_) => Ok(Event::default().comment("missed message")),
+        }
     });
 
+    let stream = futures::stream::once(async move { Ok(endpoint) }).chain(messages);
     Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
 }
 
 async fn message_handler(
-    axum::extract::State(state): axum::extract::State<ServerState>,
-    Json(payload): Json<JsonRpcRequest>,
-) -> Json<serde_json::Value> {
-    println!("Received MCP Request (SSE): {:?}", payload.method);
+    State(state): State<ServerState>,
+    Query(query): Query<SessionQuery>,
+    body: String,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let session_id = query
+        .session_id
+        .ok_or((StatusCode::BAD_REQUEST, "Missing sessionId".to_string()))?;
+    let session = state.ctx.sessions.get(&session_id).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Unknown session: {}", session_id),
+    ))?;
+    println!("Received MCP Message (SSE {})", session.id);
 
-    let response = crate::mcp::handle_mcp_request(payload, &state.handle).await;
+    // The POST is only acknowledged; replies, including JSON-RPC errors for malformed
+    // bodies, travel over the session's event stream.
+    let ctx = state.ctx.clone();
+    tokio::spawn(async move {
+        if let Some(reply) = jsonrpc::handle_message(&body, &ctx, &session).await {
+            session.send(reply);
+        }
+    });
 
-    Json(serde_json::to_value(response).unwrap())
+    Ok(StatusCode::ACCEPTED)
 }
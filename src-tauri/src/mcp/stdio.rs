@@ -1,12 +1,131 @@
-use crate::mcp::JsonRpcRequest;
+use crate::mcp::{jsonrpc, McpContext};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 use tokio::io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc, Semaphore};
+use tokio::task::AbortHandle;
 
-pub async fn start_stdio_server(handle: tauri::AppHandle) {
+/// Requests handled at the same time; further requests wait for a free slot
+/// while stdin keeps being read, so cancellations still get through.
+pub const MAX_CONCURRENT_REQUESTS: usize = 8;
+/// Requests accepted (running or waiting) at the same time. Once this many are
+/// outstanding, stdin is not read until one finishes.
+pub const MAX_PENDING_REQUESTS: usize = 64;
+
+/// Runs requests from one stdio client concurrently, bounded by a semaphore, and
+/// remembers in-flight ones by JSON-RPC id so `notifications/cancelled` can abort them.
+pub struct RequestPool {
+    max_pending: usize,
+    limiter: Arc<Semaphore>,
+    pending: Arc<Semaphore>,
+    in_flight: Arc<Mutex<HashMap<String, AbortHandle>>>,
+}
+
+impl RequestPool {
+    pub fn new(max_concurrent: usize, max_pending: usize) -> Self {
+        Self {
+            max_pending,
+            limiter: Arc::new(Semaphore::new(max_concurrent)),
+            pending: Arc::new(Semaphore::new(max_pending)),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Starts `task` once fewer than `max_pending` requests are outstanding, so a
+    /// flooding client cannot pile up an unbounded number of waiting tasks.
+    pub async fn spawn<F>(&self, id: Option<&serde_json::Value>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let Ok(pending) = self.pending.clone().acquire_owned().await else {
+            return;
+        };
+        let limiter = self.limiter.clone();
+        let in_flight = self.in_flight.clone();
+        let key = id.map(|id| id.to_string());
+        let done_key = key.clone();
+
+        // Hold the map while spawning so a fast task cannot finish before it is registered.
+        let mut tasks = self.in_flight.lock().unwrap();
+        let handle = tokio::spawn(async move {
+            let _pending = pending;
+            let Ok(_permit) = limiter.acquire_owned().await else {
+                return;
+            };
+            task.await;
+            if let Some(key) = done_key {
+                in_flight.lock().unwrap().remove(&key);
+            }
+        });
+        if let Some(key) = key {
+            tasks.insert(key, handle.abort_handle());
+        }
+    }
+
+    /// Handles `notifications/cancelled`. The aborted request gets no response.
+    pub fn cancel(&self, params: Option<&serde_json::Value>) {
+        let Some(request_id) = params.and_then(|p| p.get("requestId")) else {
+            return;
+        };
+        let key = request_id.to_string();
+        if let Some(handle) = self.in_flight.lock().unwrap().remove(&key) {
+            handle.abort();
+            let reason = params
+                .and_then(|p| p.get("reason"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("no reason given");
+            eprintln!("Cancelled MCP request {}: {}", key, reason);
+        }
+    }
+
+    /// Waits until every accepted request has finished.
+    pub async fn finish(&self) {
+        let _ = self.pending.acquire_many(self.max_pending as u32).await;
+    }
+}
+
+pub async fn start_stdio_server(ctx: McpContext) {
     let stdin = stdin();
     let mut reader = BufReader::new(stdin);
-    let mut stdout = stdout();
     let mut line = String::new();
 
+    // The stdio client is a session like any other, so role changes and resource
+    // updates reach it the same way they reach SSE clients.
+    let session = ctx.sessions.create("stdio", ctx.state.get_state());
+    let pool = RequestPool::new(MAX_CONCURRENT_REQUESTS, MAX_PENDING_REQUESTS);
+
+    // Responses and notifications share stdout; one writer keeps every line whole.
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = stdout();
+        while let Some(message) = rx.recv().await {
+            if let Err(e) = stdout.write_all(format!("{}\n", message).as_bytes()).await {
+                eprintln!("Failed to write to stdout: {}", e);
+                break;
+            }
+            let _ = stdout.flush().await;
+        }
+    });
+
+    let mut events = session.subscribe();
+    let notifications_tx = tx.clone();
+    let notifications = tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if notifications_tx.send(event.data).is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    eprintln!("Dropped {} notifications for stdio client", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
     eprintln!("MCP Stdio Server started");
 
     loop {
@@ -19,21 +138,32 @@ pub async fn start_stdio_server(handle: tauri::AppHandle) {
                     continue;
                 }
 
-                if let Ok(req) = serde_json::from_str::<JsonRpcRequest>(trimmed) {
-                    let response = crate::mcp::handle_mcp_request(req, &handle).await;
-                    let response_json = serde_json::to_string(&response).unwrap();
-
-                    if let Err(e) = stdout
-                        .write_all(format!("{}\n", response_json).as_bytes())
-                        .await
-                    {
-                        eprintln!("Failed to write to stdout: {}", e);
-                        break;
+                let message = match jsonrpc::parse(trimmed) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        eprintln!("Invalid JSON received: {}", trimmed);
+                        let _ = tx.send(jsonrpc::error_reply(error));
+                        continue;
                     }
-                    let _ = stdout.flush().await;
-                } else {
-                    eprintln!("Invalid JSON-RPC request received: {}", trimmed);
+                };
+
+                if message.get("method").and_then(|m| m.as_str()) == Some("notifications/cancelled")
+                {
+                    pool.cancel(message.get("params"));
+                    continue;
                 }
+
+                // Only single requests can be cancelled; a batch runs as one unit.
+                let id = message.get("id").cloned();
+                let ctx = ctx.clone();
+                let session = session.clone();
+                let tx = tx.clone();
+                pool.spawn(id.as_ref(), async move {
+                    if let Some(reply) = jsonrpc::dispatch(message, &ctx, &session).await {
+                        let _ = tx.send(reply);
+                    }
+                })
+                .await;
             }
             Err(e) => {
                 eprintln!("Error reading from stdin: {}", e);
@@ -41,4 +171,14 @@ pub async fn start_stdio_server(handle: tauri::AppHandle) {
             }
         }
     }
+
+    // Answer what is already in flight before shutting down. The writer also waits for
+    // every task's sender, so no accepted request goes unanswered.
+    pool.finish().await;
+    // Removed explicitly: SessionGuard logs to stdout, which belongs to the protocol here.
+    ctx.sessions.remove(&session.id);
+    ctx.tokens.flush(&ctx.db);
+    notifications.abort();
+    drop(tx);
+    let _ = writer.await;
 }
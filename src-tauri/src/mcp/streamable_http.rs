@@ -0,0 +1,263 @@
+//! MCP Streamable HTTP transport: one endpoint (`/mcp`) for POST, GET and DELETE,
+//! with the session carried in the `Mcp-Session-Id` header.
+
+use crate::mcp::jsonrpc;
+use crate::mcp::lifecycle::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::mcp::session::{Session, SessionEvent, STANDALONE_STREAM};
+use crate::mcp::sse::ServerState;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::stream::StreamExt;
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio_stream::wrappers::{BroadcastStream, UnboundedReceiverStream};
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+const TRANSPORT: &str = "streamable-http";
+
+/// Sessions without an open stream are dropped after this long without requests.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Methods answered over an SSE stream when the client accepts one, since they can run long.
+const STREAMED_METHODS: [&str; 2] = ["tools/call", "callTool"];
+
+pub async fn post_handler(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if let Err(rejection) = check_origin(&headers).and_then(|_| check_protocol_version(&headers)) {
+        return rejection.into_response();
+    }
+
+    let message = match jsonrpc::parse(&body) {
+        Ok(message) => message,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                [(header::CONTENT_TYPE, "application/json")],
+                jsonrpc::error_reply(error),
+            )
+                .into_response()
+        }
+    };
+    let methods = jsonrpc::methods(&message);
+
+    // Only a lone `initialize` request opens a session; it may not be batched.
+    let session = if message.get("method").and_then(|m| m.as_str()) == Some("initialize") {
+        state
+            .ctx
+            .sessions
+            .prune_idle(TRANSPORT, SESSION_IDLE_TIMEOUT);
+        let role = state.ctx.state.get_state();
+        let session = state.ctx.sessions.create(TRANSPORT, role);
+        println!("MCP Streamable HTTP session {} connected", session.id);
+        session
+    } else {
+        match session_from_headers(&state, &headers) {
+            Ok(session) => session,
+            Err(rejection) => return rejection.into_response(),
+        }
+    };
+
+    println!("Received MCP Request (HTTP {}): {:?}", session.id, methods);
+    session.touch();
+
+    // Bodies with only notifications or client responses get an acknowledgement, no body.
+    if !jsonrpc::expects_response(&message) {
+        let ctx = state.ctx.clone();
+        tokio::spawn(async move {
+            jsonrpc::dispatch(message, &ctx, &session).await;
+        });
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    let stream_response = accepts(&headers, "text/event-stream")
+        && methods.iter().any(|m| STREAMED_METHODS.contains(m));
+
+    let mut http_response = if stream_response {
+        // The stream opens right away; notifications and requests the server sends
+        // while handling this request go out on it, then the response closes it.
+        // Everything is recorded, so a client that loses the stream can replay it via GET.
+        let (stream, events) = session.open_stream();
+        let ctx = state.ctx.clone();
+        let request_session = session.clone();
+        tokio::spawn(stream.scope(async move {
+            if let Some(response_json) = jsonrpc::dispatch(message, &ctx, &request_session).await {
+                request_session.send(response_json);
+            }
+        }));
+        let events = UnboundedReceiverStream::new(events)
+            .map(|event| Ok::<_, Infallible>(to_sse_event(event)));
+        Sse::new(events)
+            .keep_alive(KeepAlive::default())
+            .into_response()
+    } else {
+        let Some(response_json) = jsonrpc::dispatch(message, &state.ctx, &session).await else {
+            return StatusCode::ACCEPTED.into_response();
+        };
+        ([(header::CONTENT_TYPE, "application/json")], response_json).into_response()
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&session.id) {
+        http_response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    http_response
+}
+
+/// Opens the server-to-client stream for a session. With `Last-Event-ID`, the events
+/// recorded after that id on the same stream are replayed first, so a dropped
+/// connection loses nothing.
+pub async fn get_handler(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = check_origin(&headers) {
+        return rejection.into_response();
+    }
+    if !accepts(&headers, "text/event-stream") {
+        return (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "GET requires Accept: text/event-stream",
+        )
+            .into_response();
+    }
+
+    let session = match session_from_headers(&state, &headers) {
+        Ok(session) => session,
+        Err(rejection) => return rejection.into_response(),
+    };
+    session.touch();
+
+    let last_event_id = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    // Subscribe before taking the replay snapshot so nothing falls in between;
+    // live events already covered by the replay are skipped.
+    let rx = session.subscribe();
+    let (resumed_stream, replay) = session.replay_after(last_event_id);
+    let replayed_up_to = replay.last().map(|e| e.id).unwrap_or(last_event_id);
+    let replay = futures::stream::iter(
+        replay
+            .into_iter()
+            .map(|e| Ok::<_, Infallible>(to_sse_event(e))),
+    );
+
+    // Resuming a request's response stream only replays what that request produced.
+    let stream = if resumed_stream == STANDALONE_STREAM {
+        let live = BroadcastStream::new(rx).filter_map(move |msg| async move {
+            match msg {
+                Ok(event) if event.id > replayed_up_to => Some(Ok(to_sse_event(event))),
+                Ok(_) => None,
+                Err(_) => Some(Ok(Event::default().comment("missed message"))),
+            }
+        });
+        replay.chain(live).left_stream()
+    } else {
+        replay.right_stream()
+    };
+
+    let mut response = Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&session.id) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+}
+
+/// Explicit session termination by the client.
+pub async fn delete_handler(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = check_origin(&headers) {
+        return rejection.into_response();
+    }
+    match session_from_headers(&state, &headers) {
+        Ok(session) => {
+            state.ctx.sessions.remove(&session.id);
+            println!("MCP Streamable HTTP session {} closed", session.id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+fn session_from_headers(
+    state: &ServerState,
+    headers: &HeaderMap,
+) -> Result<Arc<Session>, (StatusCode, String)> {
+    let session_id = headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Missing Mcp-Session-Id header".to_string(),
+            )
+        })?;
+
+    state
+        .ctx
+        .sessions
+        .get(session_id)
+        .filter(|s| s.transport == TRANSPORT)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Unknown session: {}", session_id),
+            )
+        })
+}
+
+fn to_sse_event(event: SessionEvent) -> Event {
+    Event::default()
+        .id(event.id.to_string())
+        .event("message")
+        .data(event.data)
+}
+
+fn accepts(headers: &HeaderMap, mime: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.contains(mime) || v.contains("*/*"))
+}
+
+/// Clients on 2025-06-18 and later repeat the negotiated revision on every request.
+fn check_protocol_version(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    match headers
+        .get(PROTOCOL_VERSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(version) if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported MCP-Protocol-Version: {}", version),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects browser requests from foreign origins (DNS rebinding protection).
+fn check_origin(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+        return Ok(());
+    };
+    let uri = origin.parse::<axum::http::Uri>().ok();
+    let host = uri.as_ref().and_then(|u| u.host()).unwrap_or_default();
+
+    if matches!(
+        host,
+        "localhost" | "127.0.0.1" | "[::1]" | "tauri.localhost"
+    ) {
+        Ok(())
+    } else {
+        Err((StatusCode::FORBIDDEN, "Origin not allowed".to_string()))
+    }
+}
@@ -1,47 +1,537 @@
-// use tiktoken_rs::cl100k_base; // Commented out until cargo is available to fetch
-use std::sync::atomic::{AtomicUsize, Ordering};
-// use std::sync::Arc;
+//! BPE token counting for MCP traffic. Every request an agent sends counts as input,
+//! every response it gets back as output, per session and in total. Both MCP traffic
+//! and built-in AI chat usage are also persisted to `token_usage` with an estimated cost;
+//! MCP usage is summed in memory and written every [`FLUSH_INTERVAL`].
 
-#[allow(dead_code)]
+use crate::db::DbState;
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcRequest, JsonRpcResponse, McpContext};
+use crate::state_machine::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::{get_bpe_from_tokenizer, CoreBPE};
+
+/// `settings` keys naming the model whose tokenizer is used, in order of preference:
+/// the model the agents run on, then the model configured for built-in AI chat.
+pub const MODEL_SETTINGS: [&str; 2] = ["mcp_model", "ai_model"];
+
+/// `settings` key overriding the context window, in tokens.
+pub const WINDOW_SETTING: &str = "context_window";
+/// Share of the context window at which the agent and the UI are warned.
+pub const WARN_RATIO: f64 = 0.80;
+/// Share of the context window above which `tools/call` is refused until a reset.
+pub const BLOCK_RATIO: f64 = 0.95;
+const DEFAULT_WINDOW: usize = 128_000;
+/// How often buffered MCP usage is written to `token_usage`.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Input/output counters; cheap enough to keep one per session.
+#[derive(Debug, Default)]
+pub struct TokenUsage {
+    input: AtomicUsize,
+    output: AtomicUsize,
+    warned: AtomicBool,
+    blocked: AtomicBool,
+}
+
+impl TokenUsage {
+    pub fn add(&self, input: usize, output: usize) {
+        self.input.fetch_add(input, Ordering::Relaxed);
+        self.output.fetch_add(output, Ordering::Relaxed);
+    }
+
+    /// Starts counting from zero, e.g. after a context reset; lifts any budget block.
+    pub fn reset(&self) {
+        self.input.store(0, Ordering::Relaxed);
+        self.output.store(0, Ordering::Relaxed);
+        self.warned.store(false, Ordering::Relaxed);
+        self.blocked.store(false, Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> usize {
+        let (input, output) = self.get_usage();
+        input + output
+    }
+
+    pub fn get_usage(&self) -> (usize, usize) {
+        (
+            self.input.load(Ordering::Relaxed),
+            self.output.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Usage of one or more requests with the same accounting key, as persisted to
+/// `token_usage`.
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    /// The local day the requests were made (`YYYY-MM-DD`).
+    pub day: String,
+    /// `mcp` or `ai_chat`.
+    pub source: &'static str,
+    pub session_id: Option<String>,
+    pub role: Option<AppState>,
+    pub task_id: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    /// `None` when the model is not in the pricing table.
+    pub cost_usd: Option<f64>,
+}
+
+impl UsageRecord {
+    fn key(&self) -> LedgerKey {
+        (
+            self.day.clone(),
+            self.session_id.clone(),
+            self.role,
+            self.task_id.clone(),
+            self.model.clone(),
+        )
+    }
+
+    fn merge(&mut self, other: &UsageRecord) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// Day, session, role, task and model of buffered MCP usage.
+type LedgerKey = (
+    String,
+    Option<String>,
+    Option<AppState>,
+    Option<String>,
+    String,
+);
+
+/// One row of a daily/weekly usage report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageBucket {
+    /// The day, or the Monday starting the week (`YYYY-MM-DD`).
+    pub period: String,
+    pub key: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: Option<f64>,
+}
+
+/// Process-wide tokenizer and totals, registered as Tauri state.
 pub struct TokenMonitor {
-    pub total_input_tokens: AtomicUsize,
-    pub total_output_tokens: AtomicUsize,
-    // tokenizer: CoreBPE,
+    model: RwLock<String>,
+    tokenizer: RwLock<Arc<CoreBPE>>,
+    /// Explicit window from `settings`, overriding the model's own.
+    window_override: AtomicUsize,
+    totals: TokenUsage,
+    /// MCP usage not yet written to `token_usage`.
+    unflushed: Mutex<HashMap<LedgerKey, UsageRecord>>,
+}
+
+/// Payload of the `token-budget` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetAlert {
+    pub session_id: String,
+    pub client: Option<String>,
+    pub level: &'static str,
+    pub used: usize,
+    pub window: usize,
 }
 
-#[allow(dead_code)]
 impl TokenMonitor {
-    pub fn new() -> Self {
+    pub fn new(model: &str) -> Self {
         Self {
-            total_input_tokens: AtomicUsize::new(0),
-            total_output_tokens: AtomicUsize::new(0),
-            // tokenizer: cl100k_base().unwrap(),
+            model: RwLock::new(model.to_string()),
+            tokenizer: RwLock::new(bpe_for(model)),
+            window_override: AtomicUsize::new(0),
+            totals: TokenUsage::default(),
+            unflushed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Uses the model and window configured in `settings`, if any.
+    pub fn from_settings(db: &DbState) -> Self {
+        let monitor = Self::new(&configured_model(db));
+        monitor.load_window(db);
+        monitor
+    }
+
+    /// Picks up a model or window change made in the settings page.
+    pub fn reload(&self, db: &DbState) {
+        self.set_model(&configured_model(db));
+        self.load_window(db);
+    }
+
+    fn load_window(&self, db: &DbState) {
+        let window = db
+            .get_setting(WINDOW_SETTING)
+            .ok()
+            .flatten()
+            .and_then(|w| w.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        self.window_override.store(window, Ordering::Relaxed);
+    }
+
+    /// Context window of the model in use, in tokens.
+    pub fn window(&self) -> usize {
+        match self.window_override.load(Ordering::Relaxed) {
+            0 => context_window(&self.model()),
+            window => window,
         }
     }
 
+    pub fn model(&self) -> String {
+        self.model.read().unwrap().clone()
+    }
+
+    pub fn set_model(&self, model: &str) {
+        *self.tokenizer.write().unwrap() = bpe_for(model);
+        *self.model.write().unwrap() = model.to_string();
+    }
+
     pub fn count_tokens(&self, text: &str) -> usize {
-        // Placeholder estimation until tiktoken-rs is compiled
-        // 1 token ~= 4 chars
-        text.len() / 4
+        // Encode outside the lock so a model switch never waits on a large payload.
+        let tokenizer = self.tokenizer.read().unwrap().clone();
+        tokenizer.encode_with_special_tokens(text).len()
+    }
 
-        // Real implementation:
-        // self.tokenizer.encode_with_special_tokens(text).len()
+    /// Counts one exchange towards the session and the process totals, and buffers it
+    /// for the ledger under the session's role and the task the call was about.
+    pub fn record(
+        &self,
+        session: &Session,
+        task_id: Option<String>,
+        request: &str,
+        response: &str,
+    ) {
+        let input = self.count_tokens(request);
+        let output = self.count_tokens(response);
+        session.tokens.add(input, output);
+        self.totals.add(input, output);
+
+        let role = session.role();
+        self.buffer(UsageRecord {
+            day: today(),
+            source: "mcp",
+            session_id: Some(session.id.clone()),
+            role: role.role_name().map(|_| role),
+            task_id,
+            provider: String::new(),
+            model: self.model(),
+            requests: 1,
+            input_tokens: input,
+            output_tokens: output,
+            cost_usd: None,
+        });
+    }
+
+    fn buffer(&self, record: UsageRecord) {
+        self.unflushed
+            .lock()
+            .unwrap()
+            .entry(record.key())
+            .and_modify(|r| r.merge(&record))
+            .or_insert(record);
     }
 
-    pub fn add_input(&self, text: &str) {
-        let count = self.count_tokens(text);
-        self.total_input_tokens.fetch_add(count, Ordering::Relaxed);
+    /// Prices a usage record and adds it to the persisted ledger right away.
+    pub fn charge(&self, db: &DbState, record: UsageRecord) {
+        if let Err(e) = db.add_token_usage(&[priced(record)]) {
+            eprintln!("Failed to record token usage: {}", e);
+        }
     }
 
-    pub fn add_output(&self, text: &str) {
-        let count = self.count_tokens(text);
-        self.total_output_tokens.fetch_add(count, Ordering::Relaxed);
+    /// Writes the buffered MCP usage in one transaction. On failure it stays buffered
+    /// for the next attempt.
+    pub fn flush(&self, db: &DbState) {
+        let records: Vec<UsageRecord> = self
+            .unflushed
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, record)| priced(record))
+            .collect();
+        if records.is_empty() {
+            return;
+        }
+        if let Err(e) = db.add_token_usage(&records) {
+            eprintln!("Failed to record token usage: {}", e);
+            for record in records {
+                self.buffer(record);
+            }
+        }
+    }
+
+    /// Flushes buffered usage every [`FLUSH_INTERVAL`] until the runtime stops.
+    pub async fn persist(self: Arc<Self>, db: DbState) {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.flush(&db);
+        }
     }
 
     pub fn get_usage(&self) -> (usize, usize) {
-        (
-            self.total_input_tokens.load(Ordering::Relaxed),
-            self.total_output_tokens.load(Ordering::Relaxed),
-        )
+        self.totals.get_usage()
+    }
+
+    /// Refuses work once the session is above [`BLOCK_RATIO`] of the window.
+    pub fn check_budget(&self, session: &Session) -> Result<(), String> {
+        let used = session.tokens.total();
+        let window = self.window();
+        if (used as f64) < window as f64 * BLOCK_RATIO {
+            return Ok(());
+        }
+        Err(format!(
+            "Token budget exhausted: this session has used {} of {} tokens ({:.0}%). Tool calls are blocked until the context is reset; ask the user to reset this session in TaskRails.",
+            used,
+            window,
+            used as f64 * 100.0 / window as f64
+        ))
+    }
+
+    /// Warns the agent (`notifications/message`) and the UI (`token-budget`) the first
+    /// time a session crosses each threshold since its last reset.
+    fn alert_thresholds(&self, ctx: &McpContext, session: &Session) {
+        let used = session.tokens.total() as f64;
+        let window = self.window();
+        let (level, flag, ratio) = if used >= window as f64 * BLOCK_RATIO {
+            ("blocked", &session.tokens.blocked, BLOCK_RATIO)
+        } else if used >= window as f64 * WARN_RATIO {
+            ("warning", &session.tokens.warned, WARN_RATIO)
+        } else {
+            return;
+        };
+        if flag.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let message = format!(
+            "Context usage above {:.0}% ({} / {} tokens){}",
+            ratio * 100.0,
+            used as usize,
+            window,
+            if level == "blocked" {
+                "; tool calls are blocked until a reset"
+            } else {
+                "; consider wrapping up before a reset"
+            }
+        );
+        session.log(
+            if level == "blocked" {
+                "error"
+            } else {
+                "warning"
+            },
+            "taskrails",
+            json!(message),
+        );
+        let _ = ctx.db.log_activity(
+            if level == "blocked" {
+                "TOKEN_LIMIT"
+            } else {
+                "TOKEN_WARNING"
+            },
+            &format!("[{}] {}", session.transport, message),
+        );
+        ctx.emit(
+            "token-budget",
+            BudgetAlert {
+                session_id: session.id.clone(),
+                client: session.client().map(|c| c.name),
+                level,
+                used: used as usize,
+                window,
+            },
+        );
+    }
+}
+
+/// The local day as stored in `token_usage`.
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+fn priced(mut record: UsageRecord) -> UsageRecord {
+    record.cost_usd = estimate_cost(&record.model, record.input_tokens, record.output_tokens);
+    record
+}
+
+fn configured_model(db: &DbState) -> String {
+    MODEL_SETTINGS
+        .iter()
+        .filter_map(|key| db.get_setting(key).ok().flatten())
+        .find(|m| !m.trim().is_empty())
+        .unwrap_or_default()
+}
+
+/// Published context windows, matched by model-name prefix (most specific first).
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5", 16_385),
+    ("gpt-5", 400_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini", 1_048_576),
+    ("grok", 131_072),
+    ("deepseek", 64_000),
+    ("llama", 128_000),
+];
+
+/// The context window for `model`, or 128k when it is not known.
+pub fn context_window(model: &str) -> usize {
+    let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_WINDOW)
+}
+
+/// USD per million input/output tokens, matched like [`CONTEXT_WINDOWS`]. List prices;
+/// cached-input discounts and batch pricing are ignored.
+const PRICING: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("gpt-5-nano", 0.05, 0.40),
+    ("gpt-5-mini", 0.25, 2.00),
+    ("gpt-5", 1.25, 10.00),
+    ("o1-mini", 1.10, 4.40),
+    ("o1", 15.00, 60.00),
+    ("o3-mini", 1.10, 4.40),
+    ("o3", 2.00, 8.00),
+    ("o4-mini", 1.10, 4.40),
+    ("claude-opus-4", 15.00, 75.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-opus", 15.00, 75.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("deepseek-chat", 0.27, 1.10),
+    ("deepseek-reasoner", 0.55, 2.19),
+    ("grok-4", 3.00, 15.00),
+    ("grok-3-mini", 0.30, 0.50),
+    ("grok-3", 3.00, 15.00),
+];
+
+/// Estimated cost in USD, or `None` for models without a known price.
+pub fn estimate_cost(model: &str, input_tokens: usize, output_tokens: usize) -> Option<f64> {
+    let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    PRICING
+        .iter()
+        .find(|(prefix, _, _)| name.starts_with(prefix))
+        .map(|(_, input, output)| {
+            (input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0
+        })
+}
+
+/// The task a `tools/call` is about, from its `task_id` argument.
+fn task_of(req: &JsonRpcRequest) -> Option<String> {
+    if req.method != "tools/call" {
+        return None;
+    }
+    req.params
+        .as_ref()?
+        .pointer("/arguments/task_id")?
+        .as_str()
+        .map(|id| id.to_string())
+}
+
+/// The model's own BPE where tiktoken knows it. Other vendors (Claude, Gemini, ...)
+/// publish no tokenizer, so they are approximated with `cl100k_base`.
+fn bpe_for(model: &str) -> Arc<CoreBPE> {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let tokenizer = get_tokenizer(name)
+        .or_else(|| {
+            ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+                .then_some(Tokenizer::O200kBase)
+        })
+        .unwrap_or(Tokenizer::Cl100kBase);
+    let bpe = get_bpe_from_tokenizer(tokenizer)
+        .or_else(|_| tiktoken_rs::cl100k_base())
+        .expect("tiktoken ships its vocabularies with the crate");
+    Arc::new(bpe)
+}
+
+/// Middleware around [`crate::mcp::handle_mcp_request`]: counts the request and the
+/// response payloads. Notifications count too, even though their reply is dropped.
+pub async fn metered(req: JsonRpcRequest, ctx: &McpContext, session: &Session) -> JsonRpcResponse {
+    let request = serde_json::to_string(&req).unwrap_or_default();
+    let task_id = task_of(&req);
+    let notification = req.is_notification();
+    let response = crate::mcp::handle_mcp_request(req, ctx, session).await;
+
+    let reply = if notification {
+        String::new()
+    } else {
+        serde_json::to_string(&response).unwrap_or_default()
+    };
+    ctx.tokens.record(session, task_id, &request, &reply);
+    ctx.tokens.alert_thresholds(ctx, session);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::session::SessionManager;
+
+    #[test]
+    fn usage_is_buffered_until_flushed() {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-tokens-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = crate::db::open(&dir).unwrap();
+        let monitor = TokenMonitor::new("gpt-4o");
+        let session = SessionManager::new().create("test", AppState::Coder);
+
+        monitor.record(&session, None, "hello", "world");
+        monitor.record(&session, None, "hello", "world");
+        assert!(db
+            .get_usage_breakdown("day", "session", &today())
+            .unwrap()
+            .is_empty());
+
+        monitor.flush(&db);
+        monitor.flush(&db);
+        let buckets = db.get_usage_breakdown("day", "session", &today()).unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].key, session.id);
+        assert_eq!(buckets[0].requests, 2);
+        assert_eq!(buckets[0].input_tokens, 2);
+        assert_eq!(buckets[0].output_tokens, 2);
+        assert!(buckets[0].cost_usd.is_some());
     }
 }
@@ -0,0 +1,52 @@
+use super::{required_str, tool_error, tool_text, Tool};
+use crate::mcp::hello::ACK_TOOL;
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct AcknowledgeRole;
+
+impl Tool for AcknowledgeRole {
+    fn name(&self) -> &'static str {
+        ACK_TOOL
+    }
+
+    fn description(&self) -> &'static str {
+        "確認已切換到新角色 (Hello Protocol)。角色變更後，請在使用其他工具前先呼叫。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "role": { "type": "string", "enum": ["coder", "reviewer", "architect"] }
+            },
+            "required": ["role"],
+            "additionalProperties": false
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move {
+            let role = required_str(&args, "role")?;
+            let current = ctx.state.get_state();
+            Ok(if current.role_name() == Some(role) {
+                tool_text(format!(
+                    "Acknowledged: you are now working as {:?}",
+                    current
+                ))
+            } else {
+                tool_error(format!(
+                    "Current role is {:?}, not '{}'; check get_context",
+                    current, role
+                ))
+            })
+        })
+    }
+}
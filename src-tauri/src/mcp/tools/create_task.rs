@@ -0,0 +1,107 @@
+use super::{
+    next_task_id, optional_str, record_task_change, required_str, task_field_schema, tool_error,
+    tool_json, Tool,
+};
+use crate::commands::TaskData;
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct CreateTask;
+
+impl Tool for CreateTask {
+    fn name(&self) -> &'static str {
+        "create_task"
+    }
+
+    fn description(&self) -> &'static str {
+        "在看板上建立新任務，或以 split_from 將既有任務拆分為子任務。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        let mut properties = task_field_schema();
+        properties["id"] =
+            json!({ "type": "string", "description": "自訂任務 ID，省略時自動產生" });
+        properties["split_from"] = json!({
+            "type": "string",
+            "description": "拆分來源任務 ID，未指定的欄位沿用來源任務"
+        });
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["title"]
+        })
+    }
+
+    fn requires_approval(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { create_task(ctx, &args) })
+    }
+}
+
+fn create_task(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let title = required_str(args, "title")?;
+    let db_state = &ctx.db;
+
+    let parent = match optional_str(args, "split_from") {
+        Some(parent_id) => match db_state.get_task(&parent_id) {
+            Ok(Some(parent)) => Some(parent),
+            Ok(None) => return Ok(tool_error(format!("Task not found: {}", parent_id))),
+            Err(e) => return Ok(tool_error(e)),
+        },
+        None => None,
+    };
+
+    let id = match optional_str(args, "id") {
+        Some(id) => id,
+        None => match next_task_id(ctx) {
+            Ok(id) => id,
+            Err(e) => return Ok(tool_error(e)),
+        },
+    };
+
+    let inherit = |key: &str, from_parent: fn(&TaskData) -> Option<String>| {
+        optional_str(args, key).or_else(|| parent.as_ref().and_then(from_parent))
+    };
+
+    let task = TaskData {
+        id: id.clone(),
+        title: title.to_string(),
+        description: optional_str(args, "description"),
+        status: optional_str(args, "status").unwrap_or_else(|| "todo".to_string()),
+        phase: inherit("phase", |p| p.phase.clone()),
+        priority: inherit("priority", |p| p.priority.clone()),
+        tag: inherit("tag", |p| p.tag.clone()),
+        assignee: inherit("assignee", |p| p.assignee.clone()),
+        is_reworked: Some(false),
+        ready_for_review: Some(false),
+    };
+
+    let message = match &parent {
+        Some(parent) => format!("Split {} from {}: {}", id, parent.id, title),
+        None => format!("Created {}: {}", id, title),
+    };
+
+    let result = db_state
+        .create_task(task)
+        .and_then(|_| record_task_change(ctx, "MCP_TASK_CREATE", &id, message))
+        .and_then(|_| db_state.get_task(&id));
+
+    Ok(match result {
+        Ok(task) => tool_json(json!({ "task": task })),
+        Err(e) => tool_error(e),
+    })
+}
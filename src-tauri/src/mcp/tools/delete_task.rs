@@ -0,0 +1,64 @@
+use super::{record_task_change, required_str, tool_error, tool_text, Tool};
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct DeleteTask;
+
+impl Tool for DeleteTask {
+    fn name(&self) -> &'static str {
+        "delete_task"
+    }
+
+    fn description(&self) -> &'static str {
+        "刪除任務。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "task_id": { "type": "string" }
+            },
+            "required": ["task_id"]
+        })
+    }
+
+    fn requires_approval(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { delete_task(ctx, &args) })
+    }
+}
+
+fn delete_task(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let task_id = required_str(args, "task_id")?;
+    let db_state = &ctx.db;
+
+    let task = match db_state.get_task(task_id) {
+        Ok(Some(task)) => task,
+        Ok(None) => return Ok(tool_error(format!("Task not found: {}", task_id))),
+        Err(e) => return Ok(tool_error(e)),
+    };
+
+    let message = format!("Deleted {}: {}", task.id, task.title);
+    let result = db_state
+        .delete_task(task_id)
+        .and_then(|_| record_task_change(ctx, "MCP_TASK_DELETE", task_id, message));
+
+    Ok(match result {
+        Ok(()) => tool_text(format!("Task {} deleted", task_id)),
+        Err(e) => tool_error(e),
+    })
+}
@@ -0,0 +1,73 @@
+use super::{tool_error, Tool};
+use crate::mcp::session::Session;
+use crate::mcp::{context, reset};
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct GetContext;
+
+impl Tool for GetContext {
+    fn name(&self) -> &'static str {
+        "get_context"
+    }
+
+    fn description(&self) -> &'static str {
+        "獲取目前專案的上下文：當前角色與角色指令、專案規格、進行中任務與最近活動。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "max_chars": {
+                    "type": "integer",
+                    "minimum": 500,
+                    "description": "文字內容的大小上限 (字元)，超過時自動摘要"
+                }
+            }
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move {
+            let mut result = get_context(ctx, &args);
+            reset::inject(session, &mut result);
+            Ok(result)
+        })
+    }
+}
+
+fn get_context(ctx: &McpContext, args: &serde_json::Value) -> serde_json::Value {
+    let budget = args
+        .get("max_chars")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(context::DEFAULT_CONTEXT_BUDGET);
+
+    let snapshot = match context::build(ctx) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return tool_error(e),
+    };
+    let (text, structured) = context::render(&snapshot, budget);
+
+    json!({
+        "content": [
+            { "type": "text", "text": text },
+            {
+                "type": "resource",
+                "resource": {
+                    "uri": "taskrails://context",
+                    "mimeType": "application/json",
+                    "text": structured.to_string()
+                }
+            }
+        ],
+        "structuredContent": structured
+    })
+}
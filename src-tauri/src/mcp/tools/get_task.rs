@@ -0,0 +1,53 @@
+use super::{required_str, tool_error, tool_json, Tool};
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct GetTask;
+
+impl Tool for GetTask {
+    fn name(&self) -> &'static str {
+        "get_task"
+    }
+
+    fn description(&self) -> &'static str {
+        "讀取單一任務的完整內容與留言。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "task_id": { "type": "string" }
+            },
+            "required": ["task_id"]
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { get_task(ctx, &args) })
+    }
+}
+
+fn get_task(ctx: &McpContext, args: &serde_json::Value) -> Result<serde_json::Value, JsonRpcError> {
+    let task_id = required_str(args, "task_id")?;
+    let db_state = &ctx.db;
+
+    let task = match db_state.get_task(task_id) {
+        Ok(Some(task)) => task,
+        Ok(None) => return Ok(tool_error(format!("Task not found: {}", task_id))),
+        Err(e) => return Ok(tool_error(e)),
+    };
+    let comments = db_state.get_task_comments(task_id).unwrap_or_default();
+
+    Ok(tool_json(json!({
+        "task": task,
+        "comments": comments
+    })))
+}
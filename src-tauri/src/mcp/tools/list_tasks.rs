@@ -0,0 +1,71 @@
+use super::{optional_str, tool_error, tool_json, Tool};
+use crate::commands::TaskData;
+use crate::commands::TASK_STATUSES;
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct ListTasks;
+
+impl Tool for ListTasks {
+    fn name(&self) -> &'static str {
+        "list_tasks"
+    }
+
+    fn description(&self) -> &'static str {
+        "列出看板上的任務，可依狀態、階段、標籤或負責角色篩選。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": TASK_STATUSES },
+                "phase": { "type": "string" },
+                "tag": { "type": "string" },
+                "assignee": { "type": "string" }
+            }
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { Ok(list_tasks(ctx, &args)) })
+    }
+}
+
+fn list_tasks(ctx: &McpContext, args: &serde_json::Value) -> serde_json::Value {
+    let db_state = &ctx.db;
+    let tasks = match db_state.get_tasks() {
+        Ok(tasks) => tasks,
+        Err(e) => return tool_error(e),
+    };
+
+    let status = optional_str(args, "status");
+    let phase = optional_str(args, "phase");
+    let tag = optional_str(args, "tag");
+    let assignee = optional_str(args, "assignee");
+
+    let matches = |filter: &Option<String>, value: Option<&String>| match filter {
+        Some(filter) => value.is_some_and(|v| v.eq_ignore_ascii_case(filter)),
+        None => true,
+    };
+
+    let tasks: Vec<&TaskData> = tasks
+        .iter()
+        .filter(|t| matches(&status, Some(&t.status)))
+        .filter(|t| matches(&phase, t.phase.as_ref()))
+        .filter(|t| matches(&tag, t.tag.as_ref()))
+        .filter(|t| matches(&assignee, t.assignee.as_ref()))
+        .collect();
+
+    tool_json(json!({
+        "count": tasks.len(),
+        "tasks": tasks
+    }))
+}
@@ -0,0 +1,232 @@
+//! MCP tools. Each tool is a self-contained module implementing [`Tool`]; the
+//! [`ToolRegistry`] backs both `tools/list` and `tools/call`, validating arguments
+//! against the tool's input schema before it runs.
+
+mod acknowledge_role;
+mod create_task;
+mod delete_task;
+mod get_context;
+mod get_task;
+mod list_tasks;
+pub mod schema;
+mod switch_role;
+mod update_mission;
+mod update_task;
+mod write_file;
+
+use crate::commands::TASK_STATUSES;
+use crate::mcp::jsonrpc::INVALID_PARAMS;
+use crate::mcp::permissions::RolePolicy;
+use crate::mcp::session::Session;
+use crate::mcp::{resources, JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// JSON Schema for `arguments`, advertised in `tools/list` and enforced before `call`.
+    fn input_schema(&self) -> serde_json::Value;
+
+    /// Writes are held in the Airlock until a human approves them.
+    fn requires_approval(&self) -> bool {
+        false
+    }
+
+    /// Runs the tool with already validated arguments. Execution failures are reported
+    /// in-band with [`tool_error`]; `Err` is for protocol errors only.
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>>;
+}
+
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All tools TaskRails ships with.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(get_context::GetContext);
+        registry.register(update_mission::UpdateMission);
+        registry.register(list_tasks::ListTasks);
+        registry.register(get_task::GetTask);
+        registry.register(create_task::CreateTask);
+        registry.register(update_task::UpdateTask);
+        registry.register(delete_task::DeleteTask);
+        registry.register(write_file::WriteFile);
+        registry.register(switch_role::SwitchRole);
+        registry.register(acknowledge_role::AcknowledgeRole);
+        registry
+    }
+
+    /// Adds a tool, replacing any tool registered under the same name.
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(Box::new(tool));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.tools
+            .iter()
+            .find(|t| t.name() == name)
+            .map(|t| t.as_ref())
+    }
+
+    /// The `tools` array of a `tools/list` result, limited to what `policy` allows.
+    pub fn definitions(&self, policy: &RolePolicy) -> serde_json::Value {
+        let tools: Vec<_> = self
+            .tools
+            .iter()
+            .filter(|tool| policy.allows_tool(tool.name()))
+            .map(|tool| {
+                json!({
+                    "name": tool.name(),
+                    "description": tool.description(),
+                    "inputSchema": tool.input_schema()
+                })
+            })
+            .collect();
+        json!(tools)
+    }
+
+    pub async fn call(
+        &self,
+        name: &str,
+        ctx: &McpContext,
+        session: &Session,
+        policy: &RolePolicy,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, JsonRpcError> {
+        let tool = self
+            .get(name)
+            .ok_or_else(|| JsonRpcError::invalid_params(format!("Tool not found: {}", name)))?;
+        if !policy.allows_tool(name) {
+            return Err(policy.tool_denied(name));
+        }
+
+        let errors = schema::validate(&tool.input_schema(), &args);
+        if !errors.is_empty() {
+            let details: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(JsonRpcError {
+                code: INVALID_PARAMS,
+                message: format!("Invalid arguments for {}: {}", name, details.join("; ")),
+                data: Some(json!({ "tool": name, "errors": errors })),
+            });
+        }
+
+        if let Err(message) = ctx.tokens.check_budget(session) {
+            return Ok(tool_error(message));
+        }
+
+        if tool.requires_approval() {
+            if let Err(message) = ctx.airlock.hold(ctx, session, name, &args).await {
+                return Ok(tool_error(message));
+            }
+        }
+
+        tool.call(ctx, session, args).await
+    }
+}
+
+/// Task fields shared by the create and update schemas.
+fn task_field_schema() -> serde_json::Value {
+    json!({
+        "title": { "type": "string", "description": "任務標題" },
+        "description": { "type": "string", "description": "任務描述 (Markdown)" },
+        "status": { "type": "string", "enum": TASK_STATUSES },
+        "phase": { "type": "string", "description": "所屬階段，例如 \"PHASE 1\"" },
+        "priority": { "type": "string", "description": "優先級 1 (最高) - 5" },
+        "tag": { "type": "string" },
+        "assignee": { "type": "string", "description": "負責角色 ID" }
+    })
+}
+
+/// Successful `tools/call` result with a single text content item.
+fn tool_text(text: impl Into<String>) -> serde_json::Value {
+    json!({
+        "content": [{ "type": "text", "text": text.into() }]
+    })
+}
+
+/// Successful `tools/call` result carrying JSON, both as text and as `structuredContent`.
+fn tool_json(value: serde_json::Value) -> serde_json::Value {
+    json!({
+        "content": [{
+            "type": "text",
+            "text": serde_json::to_string_pretty(&value).unwrap_or_default()
+        }],
+        "structuredContent": value
+    })
+}
+
+/// Tool execution failure, reported in-band so the agent can see and react to it.
+fn tool_error(text: impl Into<String>) -> serde_json::Value {
+    json!({
+        "content": [{ "type": "text", "text": text.into() }],
+        "isError": true
+    })
+}
+
+fn required_str<'a>(args: &'a serde_json::Value, key: &str) -> Result<&'a str, JsonRpcError> {
+    args.get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.trim().is_empty())
+        .ok_or_else(|| JsonRpcError::invalid_params(format!("Missing required argument '{}'", key)))
+}
+
+fn optional_str(args: &serde_json::Value, key: &str) -> Option<String> {
+    args.get(key)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+/// Logs an agent-initiated change, tells the board to reload and notifies subscribed clients.
+fn record_task_change(
+    ctx: &McpContext,
+    event_type: &str,
+    task_id: &str,
+    message: String,
+) -> Result<(), String> {
+    let role = ctx.state.get_state();
+
+    ctx.db
+        .log_activity(event_type, &format!("[{:?}] {}", role, message))?;
+    notify_task_change(ctx, task_id);
+    Ok(())
+}
+
+/// Tells the board and subscribed clients that a task and the activity log changed.
+fn notify_task_change(ctx: &McpContext, task_id: &str) {
+    ctx.emit("tasks-changed", ());
+    ctx.notify_task_changed(task_id);
+    ctx.sessions
+        .notify_resource_updated(resources::ACTIVITY_URI);
+}
+
+/// Task ids handed out by [`next_task_id`] are `TSK-0` up to `TSK-99999`.
+const TASK_ID_SPACE: u32 = 100_000;
+
+/// Generates a `TSK-####` id in the same shape the Kanban board uses, avoiding collisions.
+fn next_task_id(ctx: &McpContext) -> Result<String, String> {
+    let db_state = &ctx.db;
+    let existing: std::collections::HashSet<String> =
+        db_state.get_tasks()?.into_iter().map(|t| t.id).collect();
+
+    let start = (chrono::Utc::now().timestamp_millis() % 10000) as u32;
+    (0..TASK_ID_SPACE)
+        .map(|i| format!("TSK-{}", (start + i) % TASK_ID_SPACE))
+        .find(|id| !existing.contains(id))
+        .ok_or_else(|| format!("No free task id left in TSK-0..TSK-{}", TASK_ID_SPACE - 1))
+}
@@ -0,0 +1,262 @@
+//! The subset of JSON Schema used by tool input schemas: `type`, `enum`, `required`,
+//! `properties`, `additionalProperties: false`, `items`, `minimum`/`maximum` and
+//! `minLength`/`maxLength`.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Debug, Serialize)]
+pub struct SchemaError {
+    /// JSON Pointer to the offending argument, e.g. `/status`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Every violation of `schema` in `value`; empty when the value is valid.
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaError> {
+    let mut errors = Vec::new();
+    check(schema, value, "", &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+    let mut fail = |message: String| {
+        errors.push(SchemaError {
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    if let Some(expected) = schema.get("type").and_then(|t| t.as_str()) {
+        if !has_type(value, expected) {
+            fail(format!("expected {}, got {}", expected, type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            fail(format!("must be one of {}", options.join(", ")));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()) {
+            if n < min {
+                fail(format!("must be >= {}", min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()) {
+            if n > max {
+                fail(format!("must be <= {}", max));
+            }
+        }
+    }
+
+    if let Some(s) = value.as_str() {
+        let len = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+            if len < min {
+                fail(format!("must be at least {} characters", min));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+            if len > max {
+                fail(format!("must be at most {} characters", max));
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !object.contains_key(key) {
+                    errors.push(SchemaError {
+                        path: format!("{}/{}", path, key),
+                        message: "is required".to_string(),
+                    });
+                }
+            }
+        }
+
+        for (key, item) in object {
+            let item_path = format!("{}/{}", path, key);
+            match properties.and_then(|p| p.get(key)) {
+                Some(item_schema) => check(item_schema, item, &item_path, errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(SchemaError {
+                        path: item_path,
+                        message: "is not a known argument".to_string(),
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(item_schema, item, &format!("{}/{}", path, i), errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "task_id": { "type": "string", "minLength": 1 },
+                "status": { "type": "string", "enum": ["todo", "doing", "done"] },
+                "priority": { "type": "integer", "minimum": 1, "maximum": 5 },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["task_id", "status"],
+            "additionalProperties": false
+        })
+    }
+
+    fn paths(value: Value) -> Vec<String> {
+        validate(&schema(), &value)
+            .into_iter()
+            .map(|e| e.path)
+            .collect()
+    }
+
+    #[test]
+    fn valid_arguments_pass() {
+        let value = json!({ "task_id": "TSK-1", "status": "done", "priority": 3, "tags": ["ui"] });
+        assert!(validate(&schema(), &value).is_empty());
+    }
+
+    #[test]
+    fn missing_required_field() {
+        let errors = validate(&schema(), &json!({ "status": "done" }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/task_id");
+        assert_eq!(errors[0].message, "is required");
+    }
+
+    #[test]
+    fn wrong_type() {
+        let errors = validate(&schema(), &json!({ "task_id": 7, "status": "done" }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "/task_id: expected string, got integer"
+        );
+
+        let errors = validate(&schema(), &json!("TSK-1"));
+        assert_eq!(errors[0].to_string(), "/: expected object, got string");
+
+        assert_eq!(
+            paths(json!({ "task_id": "TSK-1", "status": "done", "tags": ["ui", 1] })),
+            ["/tags/1"]
+        );
+    }
+
+    #[test]
+    fn enum_mismatch() {
+        let errors = validate(
+            &schema(),
+            &json!({ "task_id": "TSK-1", "status": "finished" }),
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/status");
+        assert_eq!(
+            errors[0].message,
+            r#"must be one of "todo", "doing", "done""#
+        );
+    }
+
+    #[test]
+    fn additional_properties_are_rejected() {
+        assert_eq!(
+            paths(json!({ "task_id": "TSK-1", "status": "done", "assignee": "coder" })),
+            ["/assignee"]
+        );
+
+        let mut open = schema();
+        open["additionalProperties"] = json!(true);
+        let value = json!({ "task_id": "TSK-1", "status": "done", "assignee": "coder" });
+        assert!(validate(&open, &value).is_empty());
+    }
+
+    #[test]
+    fn length_and_range_limits() {
+        let errors = validate(&schema(), &json!({ "task_id": "", "status": "done" }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "/task_id: must be at least 1 characters"
+        );
+
+        assert_eq!(
+            paths(json!({ "task_id": "TSK-1", "status": "done", "priority": 9 })),
+            ["/priority"]
+        );
+    }
+
+    #[test]
+    fn every_violation_is_reported_as_error_data() {
+        let mut errors = validate(
+            &schema(),
+            &json!({ "task_id": "", "status": "finished", "extra": true }),
+        );
+        // Argument order depends on serde_json's map features
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        // Serialized as-is into the `data.errors` of the INVALID_PARAMS response
+        assert_eq!(
+            serde_json::to_value(&errors).unwrap(),
+            json!([
+                { "path": "/extra", "message": "is not a known argument" },
+                { "path": "/status", "message": r#"must be one of "todo", "doing", "done""# },
+                { "path": "/task_id", "message": "must be at least 1 characters" }
+            ])
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use super::{optional_str, required_str, tool_error, tool_text, Tool};
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use crate::state_machine::{AppState, Trigger};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct SwitchRole;
+
+impl Tool for SwitchRole {
+    fn name(&self) -> &'static str {
+        "switch_role"
+    }
+
+    fn description(&self) -> &'static str {
+        "切換工作角色 (例如開發完成後交給審查者)。不符合流程規則時會回傳錯誤。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "role": { "type": "string", "enum": ["coder", "reviewer", "architect"] },
+                "reason": { "type": "string", "description": "切換原因，會記錄在狀態歷史中" }
+            },
+            "required": ["role"],
+            "additionalProperties": false
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { switch_role(ctx, &args) })
+    }
+}
+
+fn switch_role(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let role = required_str(args, "role")?;
+    let to = AppState::from_role_name(role)
+        .ok_or_else(|| JsonRpcError::invalid_params(format!("Invalid role: {}", role)))?;
+
+    Ok(
+        match ctx
+            .state
+            .transition(&ctx.db, to, Trigger::Agent, optional_str(args, "reason"))
+        {
+            Ok(Some(transition)) => {
+                tool_text(format!("Switched from {:?} to {:?}", transition.from, to))
+            }
+            Ok(None) => tool_text(format!("Already in {:?}", to)),
+            Err(e) => tool_error(e),
+        },
+    )
+}
@@ -0,0 +1,87 @@
+use super::{notify_task_change, required_str, tool_error, tool_text, Tool};
+use crate::commands::TASK_STATUSES;
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct UpdateMission;
+
+impl Tool for UpdateMission {
+    fn name(&self) -> &'static str {
+        "update_mission"
+    }
+
+    fn description(&self) -> &'static str {
+        "更新特定任務的狀態或進度描述。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "task_id": { "type": "string" },
+                "status": { "type": "string", "enum": TASK_STATUSES },
+                "comment": { "type": "string" },
+                "ready_for_review": {
+                    "type": "boolean",
+                    "description": "標記任務已可交付審查；所有進行中 (doing) 的開發任務都標記後才能切換到 Reviewer，狀態變更時標記會清除"
+                }
+            },
+            "required": ["task_id", "status"]
+        })
+    }
+
+    fn requires_approval(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { update_mission(ctx, &args) })
+    }
+}
+
+fn update_mission(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let task_id = required_str(args, "task_id")?;
+    let status = required_str(args, "status")?;
+    let comment = args
+        .get("comment")
+        .and_then(|v| v.as_str())
+        .filter(|c| !c.trim().is_empty());
+
+    let ready_for_review = args.get("ready_for_review").and_then(|v| v.as_bool());
+
+    let role = ctx.state.get_state();
+
+    let mut message = match comment {
+        Some(comment) => format!("{} -> {}: {}", task_id, status, comment),
+        None => format!("{} -> {}", task_id, status),
+    };
+    if ready_for_review == Some(true) {
+        message.push_str(" (ready for review)");
+    }
+    let result = ctx.db.update_mission(
+        task_id,
+        status,
+        ready_for_review,
+        &format!("{:?}", role),
+        comment,
+        &format!("[{:?}] {}", role, message),
+    );
+    if result.is_ok() {
+        notify_task_change(ctx, task_id);
+    }
+
+    Ok(match result {
+        Ok(()) => tool_text(format!("Task {} updated to '{}'", task_id, status)),
+        Err(e) => tool_error(e),
+    })
+}
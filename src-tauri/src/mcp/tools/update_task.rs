@@ -0,0 +1,109 @@
+use super::{
+    optional_str, record_task_change, required_str, task_field_schema, tool_error, tool_json, Tool,
+};
+use crate::mcp::session::Session;
+use crate::mcp::{JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+pub struct UpdateTask;
+
+impl Tool for UpdateTask {
+    fn name(&self) -> &'static str {
+        "update_task"
+    }
+
+    fn description(&self) -> &'static str {
+        "修改任務欄位，只會變更有提供的欄位。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        let mut properties = task_field_schema();
+        properties["task_id"] = json!({ "type": "string" });
+        properties["is_reworked"] = json!({ "type": "boolean" });
+        properties["ready_for_review"] = json!({
+            "type": "boolean",
+            "description": "標記任務已可交付審查；所有進行中 (doing) 的開發任務都標記後才能切換到 Reviewer，狀態變更時標記會清除"
+        });
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["task_id"]
+        })
+    }
+
+    fn requires_approval(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { update_task(ctx, &args) })
+    }
+}
+
+fn update_task(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let task_id = required_str(args, "task_id")?;
+    let db_state = &ctx.db;
+
+    let mut task = match db_state.get_task(task_id) {
+        Ok(Some(task)) => task,
+        Ok(None) => return Ok(tool_error(format!("Task not found: {}", task_id))),
+        Err(e) => return Ok(tool_error(e)),
+    };
+
+    let mut changed = Vec::new();
+    if let Some(title) = optional_str(args, "title") {
+        task.title = title;
+        changed.push("title");
+    }
+    if let Some(status) = optional_str(args, "status") {
+        task.status = status;
+        changed.push("status");
+    }
+    for (key, field) in [
+        ("description", &mut task.description),
+        ("phase", &mut task.phase),
+        ("priority", &mut task.priority),
+        ("tag", &mut task.tag),
+        ("assignee", &mut task.assignee),
+    ] {
+        if let Some(value) = optional_str(args, key) {
+            *field = Some(value);
+            changed.push(key);
+        }
+    }
+    if let Some(is_reworked) = args.get("is_reworked").and_then(|v| v.as_bool()) {
+        task.is_reworked = Some(is_reworked);
+        changed.push("is_reworked");
+    }
+    if let Some(ready) = args.get("ready_for_review").and_then(|v| v.as_bool()) {
+        task.ready_for_review = Some(ready);
+        changed.push("ready_for_review");
+    }
+
+    if changed.is_empty() {
+        return Err(JsonRpcError::invalid_params(
+            "update_task requires at least one field to change",
+        ));
+    }
+
+    let message = format!("Updated {} ({})", task_id, changed.join(", "));
+    let result = db_state
+        .update_task(task)
+        .and_then(|_| record_task_change(ctx, "MCP_TASK_UPDATE", task_id, message))
+        .and_then(|_| db_state.get_task(task_id));
+
+    Ok(match result {
+        Ok(task) => tool_json(json!({ "task": task })),
+        Err(e) => tool_error(e),
+    })
+}
@@ -0,0 +1,153 @@
+use super::{required_str, tool_error, tool_text, Tool};
+use crate::mcp::session::Session;
+use crate::mcp::{resources, JsonRpcError, McpContext};
+use futures::future::BoxFuture;
+use serde_json::json;
+use std::path::{Component, Path, PathBuf};
+
+pub struct WriteFile;
+
+impl Tool for WriteFile {
+    fn name(&self) -> &'static str {
+        "write_file"
+    }
+
+    fn description(&self) -> &'static str {
+        "在工作區內寫入檔案 (需經 Airlock 人工核准)。"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "minLength": 1, "description": "相對於工作區根目錄的路徑" },
+                "content": { "type": "string", "description": "完整的檔案內容" }
+            },
+            "required": ["path", "content"],
+            "additionalProperties": false
+        })
+    }
+
+    fn requires_approval(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        ctx: &'a McpContext,
+        _session: &'a Session,
+        args: serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, JsonRpcError>> {
+        Box::pin(async move { write_file(ctx, &args) })
+    }
+}
+
+fn write_file(
+    ctx: &McpContext,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value, JsonRpcError> {
+    let relative = required_str(args, "path")?;
+    let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
+
+    let result = workspace_path(ctx, relative).and_then(|path| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("無法建立目錄: {}", e))?;
+        }
+        std::fs::write(&path, content).map_err(|e| format!("無法寫入檔案: {}", e))?;
+        ctx.db.log_activity(
+            "MCP_FILE_WRITE",
+            &format!(
+                "[{:?}] {} ({} bytes)",
+                ctx.state.get_state(),
+                relative,
+                content.len()
+            ),
+        )?;
+        ctx.sessions
+            .notify_resource_updated(resources::ACTIVITY_URI);
+        Ok(())
+    });
+
+    Ok(match result {
+        Ok(()) => tool_text(format!("Wrote {} ({} bytes)", relative, content.len())),
+        Err(e) => tool_error(e),
+    })
+}
+
+/// Resolves `relative` inside the configured workspace, refusing paths that escape it.
+fn workspace_path(ctx: &McpContext, relative: &str) -> Result<PathBuf, String> {
+    let root = ctx
+        .db
+        .get_setting("workspace_path")?
+        .filter(|p| !p.trim().is_empty())
+        .ok_or("尚未設定工作區 (workspace_path)")?;
+
+    let relative = Path::new(relative);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!(
+            "Path must stay inside the workspace: {}",
+            relative.display()
+        ));
+    }
+    let root = Path::new(&root)
+        .canonicalize()
+        .map_err(|e| format!("無法開啟工作區 {}: {}", root, e))?;
+    let path = root.join(relative);
+
+    // Symlinks inside the workspace may still point out of it: resolve the deepest
+    // part of the path that already exists, which the rest is created under.
+    let existing = path
+        .ancestors()
+        .find(|p| p.symlink_metadata().is_ok())
+        .unwrap_or(&root);
+    let resolved = existing
+        .canonicalize()
+        .map_err(|e| format!("無法解析路徑 {}: {}", existing.display(), e))?;
+    if !resolved.starts_with(&root) {
+        return Err(format!(
+            "Path must stay inside the workspace: {}",
+            relative.display()
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::state_machine::StateManager;
+
+    #[test]
+    fn symlinks_cannot_escape_the_workspace() {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-write-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let workspace = dir.join("workspace");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, workspace.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("x"), workspace.join("file")).unwrap();
+
+        let db = crate::db::open(&dir).unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO settings (key, value) VALUES ('workspace_path', ?1)",
+                [workspace.to_string_lossy()],
+            )
+            .unwrap();
+        let ctx = McpContext::headless(db, StateManager::new());
+
+        assert!(workspace_path(&ctx, "src/main.rs").is_ok());
+        assert!(workspace_path(&ctx, "link/main.rs").is_err());
+        assert!(workspace_path(&ctx, "link/new/main.rs").is_err());
+        assert!(workspace_path(&ctx, "file").is_err());
+    }
+}
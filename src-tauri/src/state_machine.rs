@@ -1,7 +1,12 @@
+use crate::commands::RoleData;
+use crate::db::DbState;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub enum AppState {
+    #[default]
     Idle,
     Coder,
     Reviewer,
@@ -9,29 +14,558 @@ pub enum AppState {
     Airlock,
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::Idle
+impl AppState {
+    /// Parses the role names used by `set_role` and MCP prompts.
+    pub fn from_role_name(name: &str) -> Option<Self> {
+        match name {
+            "coder" => Some(Self::Coder),
+            "reviewer" => Some(Self::Reviewer),
+            "architect" => Some(Self::Architect),
+            _ => None,
+        }
+    }
+
+    pub fn role_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Coder => Some("coder"),
+            Self::Reviewer => Some("reviewer"),
+            Self::Architect => Some("architect"),
+            Self::Idle | Self::Airlock => None,
+        }
+    }
+
+    /// Resolves a `set_role` argument: a built-in role name, a default role's id, or
+    /// the id of a custom `roles` row, which takes the workflow position named by its
+    /// `base_role` (Coder when unset). Returns the state and the custom row, if any.
+    pub fn resolve_role(db: &DbState, name: &str) -> Result<(Self, Option<String>), String> {
+        if let Some(state) = Self::from_role_name(name) {
+            return Ok((state, None));
+        }
+        let row = db
+            .get_roles()?
+            .into_iter()
+            .find(|r| r.id == name)
+            .ok_or_else(|| format!("Unknown role: {}", name))?;
+        let state = Self::played_by(&row)?;
+        if state.role_id() == Some(row.id.as_str()) {
+            return Ok((state, None));
+        }
+        Ok((state, Some(row.id)))
+    }
+
+    /// The state a `roles` row plays: its own for the default rows, else its base role.
+    pub fn played_by(row: &RoleData) -> Result<Self, String> {
+        if let Some(state) = [Self::Coder, Self::Reviewer, Self::Architect]
+            .into_iter()
+            .find(|s| s.role_id() == Some(row.id.as_str()))
+        {
+            return Ok(state);
+        }
+        match row.base_role.as_deref() {
+            None => Ok(Self::Coder),
+            Some(base) => Self::from_role_name(base)
+                .ok_or_else(|| format!("Role {} has an invalid base role: {}", row.id, base)),
+        }
     }
+
+    /// Id of the default `roles` row that backs this state, if any.
+    pub fn role_id(&self) -> Option<&'static str> {
+        match self {
+            Self::Coder => Some("ai_codegen"),
+            Self::Reviewer => Some("ai_review_bot"),
+            Self::Architect => Some("ai_antigravity"),
+            Self::Idle | Self::Airlock => None,
+        }
+    }
+}
+
+/// Who asked for a transition. Only the Airlock queue may enter or leave Airlock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// The desktop UI (`set_role`).
+    User,
+    /// An MCP client (`switch_role`).
+    Agent,
+    Airlock,
+}
+
+/// One entry of the persisted transition history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: AppState,
+    pub to: AppState,
+    /// Custom `roles` row active after the transition; `None` means `to`'s default row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role_id: Option<String>,
+    pub trigger: Trigger,
+    pub reason: Option<String>,
+    pub at: String,
+}
+
+/// Runs after every successful transition, outside the state lock.
+pub type TransitionHook = Arc<dyn Fn(&Transition) + Send + Sync>;
+
+/// How often [`StateManager::follow`] checks for changes made by another process.
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+/// A process that has not sent a heartbeat for this long is considered gone.
+pub const OWNER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `app_state` row.
+#[derive(Debug, Clone, Default)]
+pub struct StoredState {
+    pub state: AppState,
+    /// While in Airlock, the role to return to once the approval queue drains.
+    pub resume: Option<AppState>,
+    /// Custom `roles` row playing the current role (kept through an Airlock hold).
+    pub role_id: Option<String>,
+    /// While in Airlock, the process that entered it.
+    pub airlock_owner: Option<String>,
 }
 
+/// Cheap to clone: all clones share the same state and hooks. The `app_state` table is
+/// the source of truth, so the desktop app and a headless stdio server on the same
+/// database agree on the active role.
+#[derive(Clone)]
 pub struct StateManager {
-    pub current_state: std::sync::Mutex<AppState>,
+    /// Identifies this process in `instances` and as an Airlock owner.
+    instance: Arc<str>,
+    current: Arc<Mutex<StoredState>>,
+    /// Serializes transitions and syncs so hooks see each change exactly once.
+    gate: Arc<Mutex<()>>,
+    hooks: Arc<Mutex<Vec<TransitionHook>>>,
 }
 
 impl StateManager {
     pub fn new() -> Self {
         Self {
-            current_state: std::sync::Mutex::new(AppState::default()),
+            instance: uuid::Uuid::new_v4().to_string().into(),
+            current: Arc::new(Mutex::new(StoredState::default())),
+            gate: Arc::new(Mutex::new(())),
+            hooks: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    pub fn set_state(&self, new_state: AppState) {
-        let mut state = self.current_state.lock().unwrap();
-        *state = new_state;
+    /// Starts from the persisted state, including an Airlock hold another process
+    /// may still own; only [`release_orphaned_hold`](Self::release_orphaned_hold)
+    /// ends holds.
+    pub fn restore(db: &DbState) -> Result<Self, String> {
+        let manager = Self::new();
+        db.heartbeat(&manager.instance)?;
+        *manager.current.lock().unwrap() = db.load_app_state()?;
+        Ok(manager)
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.instance
     }
-    
+
+    /// Releases an Airlock hold whose owning process stopped sending heartbeats, back
+    /// to the held role. Only the desktop app calls this, so a short-lived stdio
+    /// process never ends a hold the app still owns.
+    pub fn release_orphaned_hold(&self, db: &DbState) -> Result<(), String> {
+        let discarded = db.discard_orphaned_approvals(OWNER_TIMEOUT)?;
+        if discarded > 0 {
+            let _ = db.log_activity(
+                "AIRLOCK_DISCARDED",
+                &format!(
+                    "{} approval request(s) of exited processes discarded",
+                    discarded
+                ),
+            );
+        }
+        let stored = db.load_app_state()?;
+        if stored.state != AppState::Airlock {
+            return Ok(());
+        }
+        if let Some(owner) = &stored.airlock_owner {
+            if db.instance_alive(owner, OWNER_TIMEOUT)? {
+                return Ok(());
+            }
+        }
+        // Live processes still waiting leave the Airlock themselves once decided
+        if db.count_pending_approvals()? > 0 {
+            return Ok(());
+        }
+        self.transition(
+            db,
+            stored.resume.unwrap_or_default(),
+            Trigger::Airlock,
+            Some("the process holding the Airlock exited".to_string()),
+        )?;
+        Ok(())
+    }
+
     pub fn get_state(&self) -> AppState {
-        *self.current_state.lock().unwrap()
+        self.current.lock().unwrap().state
+    }
+
+    /// The role an Airlock hold will return to.
+    pub fn resume_state(&self) -> Option<AppState> {
+        self.current.lock().unwrap().resume
+    }
+
+    /// Id of the `roles` row behind the active role: a selected custom role, else the
+    /// state's default row. During an Airlock hold, that of the held role.
+    pub fn active_role_id(&self) -> Option<String> {
+        let current = self.current.lock().unwrap();
+        let state = match current.state {
+            AppState::Airlock => current.resume.unwrap_or_default(),
+            state => state,
+        };
+        current
+            .role_id
+            .clone()
+            .or_else(|| state.role_id().map(|id| id.to_string()))
+    }
+
+    pub fn on_transition(&self, hook: impl Fn(&Transition) + Send + Sync + 'static) {
+        self.hooks.lock().unwrap().push(Arc::new(hook));
+    }
+
+    /// Moves to `to` if the workflow allows it, records the transition and runs the
+    /// hooks. Switching to the current state is a no-op and returns `Ok(None)`.
+    pub fn transition(
+        &self,
+        db: &DbState,
+        to: AppState,
+        trigger: Trigger,
+        reason: Option<String>,
+    ) -> Result<Option<Transition>, String> {
+        self.transition_as(db, to, None, trigger, reason)
+    }
+
+    /// Like [`transition`](Self::transition), with a custom `roles` row playing `to`.
+    /// Airlock transitions keep whichever row is active.
+    pub fn transition_as(
+        &self,
+        db: &DbState,
+        to: AppState,
+        role_id: Option<String>,
+        trigger: Trigger,
+        reason: Option<String>,
+    ) -> Result<Option<Transition>, String> {
+        let gate = self.gate.lock().unwrap();
+        // Rules are checked against the shared state, not a stale local copy.
+        let adopted = self.reload(db)?;
+        let result = self.apply(db, to, role_id, trigger, reason);
+        drop(gate);
+
+        if let Some(adopted) = &adopted {
+            self.run_hooks(adopted);
+        }
+        if let Ok(Some(transition)) = &result {
+            self.run_hooks(transition);
+        }
+        result
+    }
+
+    /// Adopts a state written by another process, running the hooks as if the
+    /// transition had happened here.
+    pub fn sync(&self, db: &DbState) -> Result<(), String> {
+        let adopted = {
+            let _gate = self.gate.lock().unwrap();
+            self.reload(db)?
+        };
+        if let Some(adopted) = &adopted {
+            self.run_hooks(adopted);
+        }
+        Ok(())
+    }
+
+    /// Polls the database for transitions made by another process and keeps this
+    /// process's heartbeat fresh until the runtime stops. `release_orphans` is set by
+    /// the desktop app only.
+    pub async fn follow(self, db: DbState, release_orphans: bool) {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = db.heartbeat(&self.instance) {
+                eprintln!("Failed to record heartbeat: {}", e);
+            }
+            if let Err(e) = self.sync(&db) {
+                eprintln!("Failed to sync app state: {}", e);
+            }
+            if release_orphans {
+                if let Err(e) = self.release_orphaned_hold(&db) {
+                    eprintln!("Failed to release orphaned Airlock hold: {}", e);
+                }
+            }
+        }
+    }
+
+    fn apply(
+        &self,
+        db: &DbState,
+        to: AppState,
+        role_id: Option<String>,
+        trigger: Trigger,
+        reason: Option<String>,
+    ) -> Result<Option<Transition>, String> {
+        let mut current = self.current.lock().unwrap();
+        let from = current.state;
+        let role_id = match trigger {
+            Trigger::Airlock => current.role_id.clone(),
+            _ => role_id,
+        };
+        if from == to && role_id == current.role_id {
+            return Ok(None);
+        }
+        check(db, from, to, trigger)?;
+
+        let (resume, airlock_owner) = match (from, to) {
+            (_, AppState::Airlock) => (Some(from), Some(self.instance.to_string())),
+            (AppState::Airlock, _) => (None, None),
+            _ => (current.resume, None),
+        };
+        let transition = Transition {
+            from,
+            to,
+            role_id: role_id.clone(),
+            trigger,
+            reason,
+            at: chrono::Utc::now().to_rfc3339(),
+        };
+        if !db.commit_transition(
+            &transition,
+            current.role_id.as_deref(),
+            resume,
+            airlock_owner.as_deref(),
+        )? {
+            return Err(
+                "The role was changed by another TaskRails process meanwhile; try again"
+                    .to_string(),
+            );
+        }
+        *current = StoredState {
+            state: to,
+            resume,
+            role_id,
+            airlock_owner,
+        };
+        if from == AppState::Reviewer && to != AppState::Airlock {
+            db.clear_ready_for_review()?;
+        }
+        Ok(Some(transition))
+    }
+
+    /// Loads the persisted state; returns the transition to report if it changed.
+    fn reload(&self, db: &DbState) -> Result<Option<Transition>, String> {
+        let stored = db.load_app_state()?;
+        let (state, role_id) = (stored.state, stored.role_id.clone());
+        let (from, from_role_id) = {
+            let mut current = self.current.lock().unwrap();
+            let previous = std::mem::replace(&mut *current, stored);
+            (previous.state, previous.role_id)
+        };
+        if from == state && from_role_id == role_id {
+            return Ok(None);
+        }
+
+        let latest = db.get_transitions(1)?.into_iter().next();
+        Ok(Some(match latest {
+            Some(latest) if latest.to == state && latest.role_id == role_id => {
+                Transition { from, ..latest }
+            }
+            _ => Transition {
+                from,
+                to: state,
+                role_id,
+                trigger: Trigger::User,
+                reason: None,
+                at: chrono::Utc::now().to_rfc3339(),
+            },
+        }))
+    }
+
+    fn run_hooks(&self, transition: &Transition) {
+        let hooks = self.hooks.lock().unwrap().clone();
+        for hook in hooks {
+            hook(transition);
+        }
+    }
+}
+
+/// The workflow rules. Everything not listed here is allowed.
+fn check(db: &DbState, from: AppState, to: AppState, trigger: Trigger) -> Result<(), String> {
+    match (from, to) {
+        (AppState::Airlock, _) if trigger != Trigger::Airlock => Err(format!(
+            "Cannot switch to {:?}: changes are waiting for approval in the Airlock",
+            to
+        )),
+        (_, AppState::Airlock) if trigger != Trigger::Airlock => {
+            Err("Only the approval queue can enter the Airlock".to_string())
+        }
+        // Returning from an Airlock hold resumes a review that was already let in
+        (AppState::Airlock, AppState::Reviewer) => Ok(()),
+        (_, AppState::Reviewer) => ready_for_review(db, from == AppState::Coder),
+        _ => Ok(()),
+    }
+}
+
+/// Review starts only once every coder task in progress is marked ready for review,
+/// whichever role the switch comes from. The coder itself must also have a task in
+/// progress to hand over.
+fn ready_for_review(db: &DbState, from_coder: bool) -> Result<(), String> {
+    let coders: Vec<String> = db
+        .get_roles()?
+        .into_iter()
+        .filter(|r| AppState::played_by(r) == Ok(AppState::Coder))
+        .map(|r| r.id)
+        .collect();
+    let in_progress: Vec<_> = db
+        .get_tasks()?
+        .into_iter()
+        .filter(|t| t.status == "doing")
+        .filter(|t| t.assignee.as_ref().is_some_and(|a| coders.contains(a)))
+        .collect();
+
+    if from_coder && in_progress.is_empty() {
+        return Err(
+            "Cannot hand over to Reviewer: no task is in progress; move the task under review to doing and set ready_for_review first"
+                .to_string(),
+        );
+    }
+    let unmarked: Vec<&str> = in_progress
+        .iter()
+        .filter(|t| t.ready_for_review != Some(true))
+        .map(|t| t.id.as_str())
+        .collect();
+    if !unmarked.is_empty() {
+        return Err(format!(
+            "Cannot hand over to Reviewer: {} not marked ready for review; set ready_for_review with update_task or update_mission first",
+            unmarked.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> DbState {
+        let dir = std::env::temp_dir().join(format!(
+            "taskrails-state-test-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        crate::db::open(&dir).unwrap()
+    }
+
+    fn coder_task(db: &DbState, id: &str, status: &str) {
+        db.create_task(crate::commands::TaskData {
+            id: id.to_string(),
+            title: id.to_string(),
+            description: None,
+            status: status.to_string(),
+            phase: None,
+            priority: None,
+            tag: None,
+            assignee: AppState::Coder.role_id().map(String::from),
+            is_reworked: None,
+            ready_for_review: None,
+        })
+        .unwrap();
+    }
+
+    fn mark_ready(db: &DbState, id: &str, status: &str) {
+        db.update_mission(id, status, Some(true), "Coder", None, id)
+            .unwrap();
+    }
+
+    #[test]
+    fn reviewer_handover_needs_ready_for_review() {
+        let db = db();
+        let state = StateManager::new();
+        coder_task(&db, "TSK-T1", "doing");
+        state
+            .transition(&db, AppState::Coder, Trigger::User, None)
+            .unwrap();
+
+        let denied = state.transition(&db, AppState::Reviewer, Trigger::User, None);
+        assert!(denied.unwrap_err().contains("TSK-T1"));
+        assert_eq!(state.get_state(), AppState::Coder);
+
+        mark_ready(&db, "TSK-T1", "doing");
+        state
+            .transition(&db, AppState::Reviewer, Trigger::User, None)
+            .unwrap();
+        assert_eq!(state.get_state(), AppState::Reviewer);
+
+        // Work going back to the coder ends the review round
+        state
+            .transition(&db, AppState::Coder, Trigger::User, None)
+            .unwrap();
+        assert_eq!(
+            db.get_task("TSK-T1").unwrap().unwrap().ready_for_review,
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn stale_done_task_does_not_open_review() {
+        let db = db();
+        let state = StateManager::new();
+        coder_task(&db, "TSK-T1", "doing");
+        mark_ready(&db, "TSK-T1", "doing");
+        db.update_task_status("TSK-T1", "done").unwrap();
+        assert_eq!(
+            db.get_task("TSK-T1").unwrap().unwrap().ready_for_review,
+            Some(false)
+        );
+
+        // Marked again after finishing: still nothing in progress to hand over
+        mark_ready(&db, "TSK-T1", "done");
+        state
+            .transition(&db, AppState::Coder, Trigger::User, None)
+            .unwrap();
+        assert!(state
+            .transition(&db, AppState::Reviewer, Trigger::User, None)
+            .is_err());
+    }
+
+    #[test]
+    fn indirect_route_to_reviewer_is_gated() {
+        let db = db();
+        let state = StateManager::new();
+        coder_task(&db, "TSK-T1", "doing");
+        state
+            .transition(&db, AppState::Coder, Trigger::User, None)
+            .unwrap();
+        state
+            .transition(&db, AppState::Architect, Trigger::User, None)
+            .unwrap();
+
+        let denied = state.transition(&db, AppState::Reviewer, Trigger::User, None);
+        assert!(denied.unwrap_err().contains("TSK-T1"));
+        assert_eq!(state.get_state(), AppState::Architect);
+    }
+
+    #[test]
+    fn only_orphaned_airlock_holds_are_released() {
+        let db = db();
+        let owner = StateManager::restore(&db).unwrap();
+        owner
+            .transition(&db, AppState::Coder, Trigger::User, None)
+            .unwrap();
+        owner
+            .transition(&db, AppState::Airlock, Trigger::Airlock, None)
+            .unwrap();
+
+        // Another process starting up leaves a live owner's hold alone
+        let desktop = StateManager::restore(&db).unwrap();
+        assert_eq!(desktop.get_state(), AppState::Airlock);
+        desktop.release_orphaned_hold(&db).unwrap();
+        assert_eq!(db.load_app_state().unwrap().state, AppState::Airlock);
+
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM instances WHERE id = ?1", [&*owner.instance])
+            .unwrap();
+        desktop.release_orphaned_hold(&db).unwrap();
+        assert_eq!(desktop.get_state(), AppState::Coder);
     }
 }
@@ -1,4 +1,9 @@
+use crate::utils::sse::SseParser;
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMessage {
@@ -23,6 +28,16 @@ pub struct OpenAICompatibleRequest {
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<OpenAIStreamOptions>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamOptions {
+    /// Ask for a final chunk carrying `usage`.
+    pub include_usage: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +49,8 @@ pub struct AnthropicRequest {
     pub max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
 }
 
 // ============ Google Native Types ============
@@ -56,6 +73,104 @@ pub struct GooglePart {
     pub text: String,
 }
 
+/// Token counts as reported by the provider.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct AiUsage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AiResponse {
+    pub text: String,
+    /// `None` when the provider did not report usage (e.g. some local servers).
+    pub usage: Option<AiUsage>,
+}
+
+/// Reads `{input_key, output_key}` counters from a provider's usage object.
+fn usage_from(usage: &serde_json::Value, input_key: &str, output_key: &str) -> Option<AiUsage> {
+    let input = usage.get(input_key).and_then(|v| v.as_u64());
+    let output = usage.get(output_key).and_then(|v| v.as_u64());
+    if input.is_none() && output.is_none() {
+        return None;
+    }
+    Some(AiUsage {
+        input_tokens: input.unwrap_or(0) as usize,
+        output_tokens: output.unwrap_or(0) as usize,
+    })
+}
+
+/// AI requests in flight, registered as Tauri state so `cancel_ai_chat` can reach them.
+#[derive(Default)]
+pub struct AiRequests {
+    running: Mutex<HashMap<String, oneshot::Sender<()>>>,
+}
+
+impl AiRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An id for a request the frontend did not name. Random, so it cannot collide
+    /// with requests recorded before a restart.
+    pub fn new_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    /// Registers a request; the receiver fires when it is cancelled.
+    pub fn begin(&self, id: &str) -> Result<oneshot::Receiver<()>, String> {
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(id) {
+            return Err(format!("AI request {} is already running", id));
+        }
+        let (cancel, cancelled) = oneshot::channel();
+        running.insert(id.to_string(), cancel);
+        Ok(cancelled)
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.running.lock().unwrap().remove(id);
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<(), String> {
+        let cancel = self
+            .running
+            .lock()
+            .unwrap()
+            .remove(id)
+            .ok_or_else(|| format!("AI request {} is not running", id))?;
+        let _ = cancel.send(());
+        Ok(())
+    }
+}
+
+/// The wire format a provider speaks.
+#[derive(Debug, Clone, Copy)]
+enum Api {
+    OpenAICompatible,
+    Anthropic,
+    Google,
+}
+
+impl Api {
+    fn of(provider: &str) -> Self {
+        match provider {
+            "anthropic" => Api::Anthropic,
+            "google" => Api::Google,
+            _ => Api::OpenAICompatible,
+        }
+    }
+
+    /// Prefixes for transport and provider errors, as shown in the chat window.
+    fn error_labels(self) -> (&'static str, &'static str) {
+        match self {
+            Api::OpenAICompatible => ("Request failed", "AI Provider Error"),
+            Api::Anthropic => ("Anthropic request failed", "Anthropic Error"),
+            Api::Google => ("Google request failed", "Google API Error"),
+        }
+    }
+}
+
 pub struct AiClient {
     client: reqwest::Client,
 }
@@ -67,15 +182,111 @@ impl AiClient {
         }
     }
 
-    pub async fn execute(&self, req: AiRequest) -> Result<String, String> {
-        match req.provider.as_str() {
-            "anthropic" => self.execute_anthropic(req).await,
-            "google" => self.execute_google(req).await,
-            _ => self.execute_openai_compatible(req).await,
+    pub async fn execute(&self, req: AiRequest) -> Result<AiResponse, String> {
+        let api = Api::of(&req.provider);
+        let response = self.send(api, req, false).await?;
+        let res_json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+
+        match api {
+            // choices[0].message.content
+            Api::OpenAICompatible => Ok(AiResponse {
+                text: res_json["choices"][0]["message"]["content"]
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| format!("Invalid response format: {:?}", res_json))?,
+                usage: usage_from(&res_json["usage"], "prompt_tokens", "completion_tokens"),
+            }),
+            // content[0].text
+            Api::Anthropic => Ok(AiResponse {
+                text: res_json["content"][0]["text"]
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| format!("Invalid Anthropic response: {:?}", res_json))?,
+                usage: usage_from(&res_json["usage"], "input_tokens", "output_tokens"),
+            }),
+            // candidates[0].content.parts[0].text
+            Api::Google => Ok(AiResponse {
+                text: res_json["candidates"][0]["content"]["parts"][0]["text"]
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| format!("Invalid Google response: {:?}", res_json))?,
+                usage: usage_from(
+                    &res_json["usageMetadata"],
+                    "promptTokenCount",
+                    "candidatesTokenCount",
+                ),
+            }),
+        }
+    }
+
+    /// Like [`execute`](Self::execute), but streams the answer over SSE: `on_delta`
+    /// gets each text fragment as it arrives, the result holds the assembled text
+    /// and the usage reported at the end of the stream.
+    pub async fn execute_stream<F>(
+        &self,
+        req: AiRequest,
+        mut on_delta: F,
+    ) -> Result<AiResponse, String>
+    where
+        F: FnMut(&str),
+    {
+        let api = Api::of(&req.provider);
+        let response = self.send(api, req, true).await?;
+
+        let mut parser = SseParser::new();
+        let mut body = response.bytes_stream();
+        let mut text = String::new();
+        let mut usage = None;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| format!("{}: {}", api.error_labels().0, e))?;
+            for event in parser.push(&chunk) {
+                // OpenAI-compatible streams end with a non-JSON sentinel
+                if event.data == "[DONE]" {
+                    continue;
+                }
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&event.data) else {
+                    continue;
+                };
+                let delta = match api {
+                    Api::OpenAICompatible => openai_chunk(&json, &mut usage)?,
+                    Api::Anthropic => anthropic_chunk(&json, &mut usage)?,
+                    Api::Google => google_chunk(&json, &mut usage)?,
+                };
+                if !delta.is_empty() {
+                    on_delta(&delta);
+                    text.push_str(&delta);
+                }
+            }
+        }
+        Ok(AiResponse { text, usage })
+    }
+
+    async fn send(
+        &self,
+        api: Api,
+        req: AiRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, String> {
+        let (failed, provider_error) = api.error_labels();
+        let request = match api {
+            Api::OpenAICompatible => self.openai_compatible_request(req, stream),
+            Api::Anthropic => self.anthropic_request(req, stream),
+            Api::Google => self.google_request(req, stream),
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", failed, e))?;
+
+        if !response.status().is_success() {
+            let err_text = response.text().await.unwrap_or_default();
+            return Err(format!("{}: {}", provider_error, err_text));
         }
+        Ok(response)
     }
 
-    async fn execute_openai_compatible(&self, req: AiRequest) -> Result<String, String> {
+    fn openai_compatible_request(&self, req: AiRequest, stream: bool) -> reqwest::RequestBuilder {
         let url = if req.provider == "custom" {
             req.endpoint
                 .clone()
@@ -100,32 +311,19 @@ impl AiClient {
             messages: req.messages,
             temperature: Some(0.7),
             max_tokens: Some(4096),
+            stream: stream.then_some(true),
+            stream_options: stream.then_some(OpenAIStreamOptions {
+                include_usage: true,
+            }),
         };
 
-        let response = self
-            .client
+        self.client
             .post(&url)
             .header("Authorization", format!("Bearer {}", req.api_key))
             .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if !response.status().is_success() {
-            let err_text = response.text().await.unwrap_or_default();
-            return Err(format!("AI Provider Error: {}", err_text));
-        }
-
-        let res_json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-        // Extract content from choices[0].message.content
-        res_json["choices"][0]["message"]["content"]
-            .as_str()
-            .map(|s| s.to_string())
-            .ok_or_else(|| format!("Invalid response format: {:?}", res_json))
     }
 
-    async fn execute_anthropic(&self, req: AiRequest) -> Result<String, String> {
+    fn anthropic_request(&self, req: AiRequest, stream: bool) -> reqwest::RequestBuilder {
         let url = "https://api.anthropic.com/v1/messages";
 
         // Anthropic requires system prompt to be separate from messages
@@ -146,37 +344,28 @@ impl AiClient {
             system: system_prompt,
             max_tokens: 4096,
             temperature: Some(0.7),
+            stream: stream.then_some(true),
         };
 
-        let response = self
-            .client
+        self.client
             .post(url)
             .header("x-api-key", &req.api_key)
             .header("anthropic-version", "2023-06-01")
             .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Anthropic request failed: {}", e))?;
-
-        if !response.status().is_success() {
-            let err_text = response.text().await.unwrap_or_default();
-            return Err(format!("Anthropic Error: {}", err_text));
-        }
-
-        let res_json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-        // Anthropic response format: content[0].text
-        res_json["content"][0]["text"]
-            .as_str()
-            .map(|s| s.to_string())
-            .ok_or_else(|| format!("Invalid Anthropic response: {:?}", res_json))
     }
 
-    async fn execute_google(&self, req: AiRequest) -> Result<String, String> {
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-            req.model, req.api_key
-        );
+    fn google_request(&self, req: AiRequest, stream: bool) -> reqwest::RequestBuilder {
+        let url = if stream {
+            format!(
+                "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?alt=sse&key={}",
+                req.model, req.api_key
+            )
+        } else {
+            format!(
+                "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
+                req.model, req.api_key
+            )
+        };
 
         let mut system_instruction = None;
         let mut contents = Vec::new();
@@ -205,26 +394,77 @@ impl AiClient {
             system_instruction,
         };
 
-        let response = self
-            .client
+        self.client
             .post(&url)
             .header("Content-Type", "application/json")
             .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Google request failed: {}", e))?;
+    }
+}
 
-        if !response.status().is_success() {
-            let err_text = response.text().await.unwrap_or_default();
-            return Err(format!("Google API Error: {}", err_text));
-        }
+// ============ Stream chunk parsers ============
+// Each returns the text delta carried by one SSE event and folds any usage into `usage`.
 
-        let res_json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+/// `choices[0].delta.content`; usage arrives in a final chunk with empty `choices`.
+fn openai_chunk(json: &serde_json::Value, usage: &mut Option<AiUsage>) -> Result<String, String> {
+    if let Some(error) = json.get("error") {
+        return Err(format!("AI Provider Error: {}", error));
+    }
+    if let Some(reported) = usage_from(&json["usage"], "prompt_tokens", "completion_tokens") {
+        *usage = Some(reported);
+    }
+    Ok(json["choices"][0]["delta"]["content"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Input tokens come with `message_start`, output tokens with `message_delta`.
+fn anthropic_chunk(
+    json: &serde_json::Value,
+    usage: &mut Option<AiUsage>,
+) -> Result<String, String> {
+    match json["type"].as_str().unwrap_or_default() {
+        "message_start" => {
+            if let Some(input) = json["message"]["usage"]["input_tokens"].as_u64() {
+                usage.get_or_insert_with(AiUsage::default).input_tokens = input as usize;
+            }
+        }
+        "message_delta" => {
+            if let Some(output) = json["usage"]["output_tokens"].as_u64() {
+                usage.get_or_insert_with(AiUsage::default).output_tokens = output as usize;
+            }
+        }
+        "content_block_delta" => {
+            return Ok(json["delta"]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string());
+        }
+        "error" => {
+            return Err(format!(
+                "Anthropic Error: {}",
+                json["error"]["message"].as_str().unwrap_or_default()
+            ));
+        }
+        _ => {}
+    }
+    Ok(String::new())
+}
 
-        // Google response format: candidates[0].content.parts[0].text
-        res_json["candidates"][0]["content"]["parts"][0]["text"]
-            .as_str()
-            .map(|s| s.to_string())
-            .ok_or_else(|| format!("Invalid Google response: {:?}", res_json))
+/// Every chunk is a partial `GenerateContentResponse`; `usageMetadata` is cumulative.
+fn google_chunk(json: &serde_json::Value, usage: &mut Option<AiUsage>) -> Result<String, String> {
+    if let Some(error) = json.get("error") {
+        return Err(format!("Google API Error: {}", error));
+    }
+    if let Some(reported) = usage_from(
+        &json["usageMetadata"],
+        "promptTokenCount",
+        "candidatesTokenCount",
+    ) {
+        *usage = Some(reported);
     }
+    Ok(json["candidates"][0]["content"]["parts"]
+        .as_array()
+        .map(|parts| parts.iter().filter_map(|p| p["text"].as_str()).collect())
+        .unwrap_or_default())
 }
@@ -1,2 +1,3 @@
 pub mod ai;
 pub mod cli;
+pub mod sse;
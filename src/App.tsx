@@ -15,6 +15,7 @@ import { useTranslation } from "./hooks/useTranslation";
 import Toast, { ToastType } from "./components/common/Toast";
 import * as dbApi from "./api/db";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { generateProjectContext } from "./utils/mdExport";
 import { parseProjectContext } from "./utils/mdImport";
 
@@ -32,6 +33,7 @@ function App() {
 
   const t = useTranslation();
   const [isAirlockOpen, setAirlockOpen] = useState(false);
+  const [airlockRequests, setAirlockRequests] = useState<dbApi.ApprovalRequest[]>([]);
   const [currentView, setCurrentView] = useState('kanban');
   const [toasts, setToasts] = useState<ToastItem[]>([]);
 
@@ -88,6 +90,83 @@ function App() {
     loadData();
   }, []);
 
+  // Reload the board when an MCP agent changes tasks
+  useEffect(() => {
+    const unlisten = listen('tasks-changed', async () => {
+      setTasks(await dbApi.fetchTasks());
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
+  // Agent writes held by the Airlock open the approval dialog
+  useEffect(() => {
+    dbApi.fetchAirlockRequests().then(requests => {
+      setAirlockRequests(requests);
+      if (requests.length > 0) setAirlockOpen(true);
+    });
+    const unlistenRequest = listen<dbApi.ApprovalRequest>('airlock-request', (event) => {
+      setAirlockRequests(prev => [...prev, event.payload]);
+      setAirlockOpen(true);
+    });
+    const unlistenResolved = listen<string>('airlock-resolved', (event) => {
+      setAirlockRequests(prev => prev.filter(r => r.id !== event.payload));
+    });
+    return () => {
+      unlistenRequest.then(fn => fn());
+      unlistenResolved.then(fn => fn());
+    };
+  }, []);
+
+  // An agent kept working under its old role after a switch (Hello Protocol)
+  useEffect(() => {
+    const unlisten = listen<{ client: string | null; expected: string; previous: string; tool: string }>('identity-mismatch', (event) => {
+      const { client, expected, previous, tool } = event.payload;
+      showToast(`${client ?? 'Agent'} 仍以 ${previous} 身分呼叫 ${tool}，尚未確認切換為 ${expected}`, 'error');
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [showToast]);
+
+  // An agent's context window is filling up; above the block threshold its tool calls are refused
+  useEffect(() => {
+    const unlisten = listen<dbApi.TokenBudgetAlert>('token-budget', (event) => {
+      const { session_id, client, level, used, window } = event.payload;
+      const name = client ?? 'Agent';
+      const percent = Math.round((used / window) * 100);
+      if (level === 'warning') {
+        showToast(`${name} 的上下文已使用 ${percent}% (${used} / ${window} tokens)`, 'warning');
+        return;
+      }
+      showToast(`${name} 的上下文已使用 ${percent}%，工具呼叫已暫停`, 'error');
+      if (confirm(`${name} 已超過上下文上限，是否重置此工作階段的上下文？`)) {
+        dbApi.resetMcpSession(session_id)
+          .then(() => showToast(`${name} 的上下文已重置`, 'success'))
+          .catch(err => showToast(String(err), 'error'));
+      }
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [showToast]);
+
+  const resolveAirlock = async (approved: boolean) => {
+    const request = airlockRequests[0];
+    if (!request) {
+      setAirlockOpen(false);
+      return;
+    }
+    try {
+      await dbApi.resolveAirlockRequest(request.id, approved);
+    } catch (err) {
+      showToast(String(err), 'error');
+    }
+    setAirlockRequests(prev => prev.filter(r => r.id !== request.id));
+    if (airlockRequests.length <= 1) setAirlockOpen(false);
+  };
+
   // Sync tasks & roles to workspace file whenever they change
   useEffect(() => {
     if (tasks.length > 0 || roles.length > 0) {
@@ -358,15 +437,11 @@ function App() {
       {/* Global Overlay */}
       <AirlockModal 
         isOpen={isAirlockOpen} 
+        request={airlockRequests[0]}
+        pendingCount={airlockRequests.length}
         onClose={() => setAirlockOpen(false)}
-        onApprove={() => {
-            console.log("Approved");
-            setAirlockOpen(false);
-        }}
-        onReject={() => {
-            console.log("Rejected");
-            setAirlockOpen(false);
-        }}
+        onApprove={() => resolveAirlock(true)}
+        onReject={() => resolveAirlock(false)}
       />
     </div>
   );
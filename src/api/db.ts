@@ -16,6 +16,7 @@ interface DbTask {
     tag: string | null;
     assignee: string | null;
     is_reworked: boolean | null;
+    ready_for_review: boolean | null;
 }
 
 interface DbRole {
@@ -25,6 +26,9 @@ interface DbRole {
     role_type: string;
     system_prompt: string | null;
     is_default: boolean;
+    allowed_tools: string[] | null;
+    allowed_resources: string[] | null;
+    base_role: string | null;
 }
 
 // ============ Task API ============
@@ -41,6 +45,7 @@ export async function fetchTasks(): Promise<Task[]> {
             tag: t.tag || undefined,
             assignee: t.assignee || undefined,
             isReworked: t.is_reworked || false,
+            readyForReview: t.ready_for_review || false,
         }));
     } catch (err) {
         console.error('[DB] Failed to fetch tasks:', err);
@@ -61,6 +66,7 @@ export async function createTask(task: Task): Promise<void> {
                 tag: task.tag || null,
                 assignee: task.assignee || null,
                 is_reworked: task.isReworked || false,
+                ready_for_review: task.readyForReview ?? null,
             }
         });
     } catch (err) {
@@ -82,6 +88,8 @@ export async function updateTask(task: Task): Promise<void> {
                 tag: task.tag || null,
                 assignee: task.assignee || null,
                 is_reworked: task.isReworked || false,
+                // Only agents mark tasks for review; the board leaves the mark as it is
+                ready_for_review: null,
             }
         });
     } catch (err) {
@@ -119,6 +127,9 @@ export async function fetchRoles(): Promise<AgentRole[]> {
             type: r.role_type as 'ai' | 'human',
             systemPrompt: r.system_prompt || undefined,
             isDefault: r.is_default,
+            allowedTools: r.allowed_tools || undefined,
+            allowedResources: r.allowed_resources || undefined,
+            baseRole: (r.base_role as AgentRole['baseRole']) || undefined,
         }));
     } catch (err) {
         console.error('[DB] Failed to fetch roles:', err);
@@ -136,6 +147,9 @@ export async function createRole(role: AgentRole): Promise<void> {
                 role_type: role.type,
                 system_prompt: role.systemPrompt || null,
                 is_default: role.isDefault || false,
+                allowed_tools: role.allowedTools ?? null,
+                allowed_resources: role.allowedResources ?? null,
+                base_role: role.baseRole ?? null,
             }
         });
     } catch (err) {
@@ -154,6 +168,9 @@ export async function updateRole(role: AgentRole): Promise<void> {
                 role_type: role.type,
                 system_prompt: role.systemPrompt || null,
                 is_default: role.isDefault || false,
+                allowed_tools: role.allowedTools ?? null,
+                allowed_resources: role.allowedResources ?? null,
+                base_role: role.baseRole ?? null,
             }
         });
     } catch (err) {
@@ -162,6 +179,24 @@ export async function updateRole(role: AgentRole): Promise<void> {
     }
 }
 
+/** Sets the MCP tool/resource allowlists of a role; omit a list to restore the built-in policy. */
+export async function setRolePermissions(
+    id: string,
+    allowedTools?: string[],
+    allowedResources?: string[],
+): Promise<void> {
+    try {
+        await invoke('set_role_permissions', {
+            id,
+            allowedTools: allowedTools ?? null,
+            allowedResources: allowedResources ?? null,
+        });
+    } catch (err) {
+        console.error('[DB] Failed to set role permissions:', err);
+        throw err;
+    }
+}
+
 export async function deleteRole(id: string): Promise<void> {
     try {
         await invoke('delete_role', { id });
@@ -170,3 +205,98 @@ export async function deleteRole(id: string): Promise<void> {
         throw err;
     }
 }
+
+// ============ Airlock API ============
+/** An agent write held until a human approves or rejects it. */
+export interface ApprovalRequest {
+    id: string;
+    tool: string;
+    arguments: Record<string, unknown>;
+    role: string;
+    session_id: string;
+    client: string | null;
+    requested_at: string;
+    expires_at: string;
+}
+
+export async function fetchAirlockRequests(): Promise<ApprovalRequest[]> {
+    try {
+        return await invoke<ApprovalRequest[]>('get_airlock_requests');
+    } catch (err) {
+        console.error('[DB] Failed to fetch airlock requests:', err);
+        return [];
+    }
+}
+
+export async function resolveAirlockRequest(id: string, approved: boolean, reason?: string): Promise<void> {
+    try {
+        await invoke('resolve_airlock_request', { id, approved, reason: reason ?? null });
+    } catch (err) {
+        console.error('[DB] Failed to resolve airlock request:', err);
+        throw err;
+    }
+}
+
+// ============ State API ============
+export interface StateTransition {
+    from: string;
+    to: string;
+    role_id?: string; // 自訂角色 id，未設定時為該狀態的預設角色
+    trigger: 'user' | 'agent' | 'airlock';
+    reason: string | null;
+    at: string;
+}
+
+/**
+ * Switches the active role to a built-in role name or a custom role id; rejects with
+ * the rule that forbids an illegal transition.
+ */
+export async function setRole(role: string): Promise<void> {
+    await invoke('set_role', { role });
+}
+
+export async function fetchStateHistory(limit?: number): Promise<StateTransition[]> {
+    try {
+        return await invoke<StateTransition[]>('get_state_history', { limit: limit ?? null });
+    } catch (err) {
+        console.error('[DB] Failed to fetch state history:', err);
+        return [];
+    }
+}
+
+// ============ Token Budget API ============
+export interface TokenBudgetAlert {
+    session_id: string;
+    client: string | null;
+    level: 'warning' | 'blocked';
+    used: number;
+    window: number;
+}
+
+/** Resets an MCP session's context, lifting a token budget block. */
+export async function resetMcpSession(id: string): Promise<void> {
+    await invoke('reset_mcp_session', { id });
+}
+
+// ============ Usage Report API ============
+export interface UsageBucket {
+    /** The day, or the Monday starting the week (YYYY-MM-DD). */
+    period: string;
+    key: string;
+    requests: number;
+    input_tokens: number;
+    output_tokens: number;
+    /** null when none of the models involved has a known price. */
+    cost_usd: number | null;
+}
+
+export type UsageGrouping = 'session' | 'role' | 'task' | 'provider' | 'model' | 'source';
+
+export async function fetchUsageReport(period: 'day' | 'week', groupBy: UsageGrouping, days?: number): Promise<UsageBucket[]> {
+    try {
+        return await invoke<UsageBucket[]>('get_usage_report', { period, groupBy, days: days ?? null });
+    } catch (err) {
+        console.error('[DB] Failed to fetch usage report:', err);
+        return [];
+    }
+}
@@ -1,8 +1,8 @@
 import { useEffect } from 'react';
-import { CheckCircle, AlertCircle, Info, X } from 'lucide-react';
+import { CheckCircle, AlertCircle, AlertTriangle, Info, X } from 'lucide-react';
 import clsx from 'clsx';
 
-export type ToastType = 'success' | 'error' | 'info';
+export type ToastType = 'success' | 'error' | 'warning' | 'info';
 
 export interface ToastProps {
     id: string;
@@ -22,12 +22,14 @@ export default function Toast({ id, message, type, onClose }: ToastProps) {
     const icons = {
         success: <CheckCircle className="text-green-400" size={18} />,
         error: <AlertCircle className="text-red-400" size={18} />,
+        warning: <AlertTriangle className="text-yellow-400" size={18} />,
         info: <Info className="text-blue-400" size={18} />,
     };
 
     const colors = {
         success: "border-green-500/30 bg-green-500/10",
         error: "border-red-500/30 bg-red-500/10",
+        warning: "border-yellow-500/30 bg-yellow-500/10",
         info: "border-blue-500/30 bg-blue-500/10",
     };
 
@@ -1,7 +1,8 @@
-import { useState, useEffect } from 'react';
+import { useState, useEffect, useRef } from 'react';
 import { Sparkles, Send, Trash2, Copy, Square, Settings as SettingsIcon, MessageSquarePlus, History, Save } from 'lucide-react';
 import { useTranslation } from '../../hooks/useTranslation';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import clsx from 'clsx';
 import { PROVIDER_MODELS } from '../../constants/ai-models';
 
@@ -27,6 +28,9 @@ export default function AiChatWindow() {
     const [showHistory, setShowHistory] = useState(true);
 
     const [isThinking, setIsThinking] = useState(false);
+    // Answer received so far while the reply is still streaming
+    const [streamingText, setStreamingText] = useState('');
+    const activeRequestId = useRef<string | null>(null);
     const [currentProvider, setCurrentProvider] = useState<string>('openai');
     const [currentModel, setCurrentModel] = useState<string>('gpt-4o');
     const [availableProviders, setAvailableProviders] = useState<string[]>(['openai', 'google', 'anthropic']);
@@ -171,6 +175,15 @@ export default function AiChatWindow() {
 
         setChatInput('');
         setIsThinking(true);
+        setStreamingText('');
+
+        const requestId = crypto.randomUUID();
+        activeRequestId.current = requestId;
+        const unlisten = await listen<{ request_id: string; delta: string }>('ai-chat-chunk', (event) => {
+            if (event.payload.request_id === requestId) {
+                setStreamingText(prev => prev + event.payload.delta);
+            }
+        });
 
         try {
             const apiMessages = [
@@ -178,11 +191,16 @@ export default function AiChatWindow() {
                 ...newMessages
             ];
 
-            const response = await invoke<string>('execute_ai_chat', { 
+            const result = await invoke<{ status: 'completed' | 'cancelled'; text: string }>('stream_ai_chat', {
+                requestId,
                 messages: apiMessages,
                 overrideProvider: currentProvider,
                 overrideModel: currentModel
             });
+            // A stopped answer keeps whatever had arrived
+            const response = result.status === 'cancelled'
+                ? `${result.text}\n\n${st.aiChat.stopped}`.trim()
+                : result.text;
             
             setIsThinking(prev => {
                 if (prev) {
@@ -207,11 +225,26 @@ export default function AiChatWindow() {
                 }
                 return false;
             });
+        } finally {
+            unlisten();
+            setStreamingText('');
+            activeRequestId.current = null;
         }
     };
 
-    const handleStopGeneration = () => {
-        setIsThinking(false);
+    const handleStopGeneration = async () => {
+        const id = activeRequestId.current;
+        if (!id) {
+            setIsThinking(false);
+            return;
+        }
+        try {
+            // The pending stream_ai_chat call then resolves with the partial answer
+            await invoke('cancel_ai_chat', { id });
+        } catch (err) {
+            console.error('Failed to cancel AI request:', err);
+            setIsThinking(false);
+        }
     };
 
     const handleCopyMessage = (content: string) => {
@@ -372,6 +405,13 @@ export default function AiChatWindow() {
                             </span>
                         </div>
                     ))}
+                     {isThinking && streamingText && (
+                        <div className="flex flex-col gap-1 items-start">
+                            <div className="max-w-[90%] px-4 py-3 rounded-2xl text-[13px] leading-relaxed shadow-sm bg-[#16161A] text-gray-300 border border-white/5 rounded-tl-none font-medium select-text">
+                                <p className="whitespace-pre-wrap">{streamingText}</p>
+                            </div>
+                        </div>
+                    )}
                      {isThinking && (
                         <div className="flex items-center gap-3 px-4 py-3 text-primary animate-pulse border border-primary/10 rounded-xl w-fit bg-primary/5">
                             <div className="flex gap-1">
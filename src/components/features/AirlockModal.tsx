@@ -1,15 +1,35 @@
 import { ShieldAlert, X, Check, XCircle } from "lucide-react";
 import { useEffect, useState } from "react";
 import { useTranslation } from "../../hooks/useTranslation";
+import { ApprovalRequest } from "../../api/db";
 
 interface AirlockModalProps {
     isOpen: boolean;
+    request?: ApprovalRequest;
+    pendingCount: number;
     onClose: () => void;
     onApprove: () => void;
     onReject: () => void;
 }
 
-export default function AirlockModal({ isOpen, onClose, onApprove, onReject }: AirlockModalProps) {
+/** Deleting tasks and writing files cannot be undone from the board. */
+function riskOf(tool: string): "low" | "medium" | "high" {
+    if (tool === "delete_task" || tool === "write_file") return "high";
+    if (tool === "update_mission") return "low";
+    return "medium";
+}
+
+/** What the call touches: a workspace path or a task id. */
+function requestTarget(request: ApprovalRequest): string {
+    const args = request.arguments;
+    if (typeof args.path === "string") return args.path;
+    if (typeof args.task_id === "string") return args.task_id;
+    if (typeof args.id === "string") return args.id;
+    if (typeof args.title === "string") return args.title;
+    return request.tool;
+}
+
+export default function AirlockModal({ isOpen, request, pendingCount, onClose, onApprove, onReject }: AirlockModalProps) {
     const t = useTranslation().airlock;
     const [visible, setVisible] = useState(false);
 
@@ -38,33 +58,34 @@ export default function AirlockModal({ isOpen, onClose, onApprove, onReject }: A
                     </button>
                 </div>
 
-                {/* Diff View (Mock) */}
+                {/* Held Tool Call */}
                 <div className="flex-1 overflow-hidden flex flex-col">
                     <div className="bg-[#111] border-b border-border-dark px-4 py-2 font-mono text-sm text-gray-400 flex items-center gap-4">
-                        <span className="text-white">src-tauri/src/lib.rs</span>
-                        <span className="px-2 py-0.5 rounded bg-blue-500/20 text-blue-400 text-xs">MODIFIED</span>
+                        <span className="text-white">{request ? requestTarget(request) : "—"}</span>
+                        {request && (
+                            <span className="px-2 py-0.5 rounded bg-blue-500/20 text-blue-400 text-xs uppercase">{request.tool}</span>
+                        )}
+                        {request && (
+                            <span className="ml-auto text-xs">{request.role}{request.client ? ` • ${request.client}` : ""}</span>
+                        )}
                     </div>
                     <div className="flex-1 overflow-y-auto p-4 font-mono text-sm leading-relaxed">
-                        {/* Mock Diff Lines */}
-                        <div className="text-gray-500 select-none">@@ -15,6 +15,12 @@</div>
-                        <div className="text-gray-400">{'     tauri::Builder::default()'}</div>
-                        <div className="text-gray-400">{'         .setup(|app| {'}</div>
-                        <div className="text-red-400 bg-red-900/10">-{'            let db_state = db::init(app.handle())?;'}</div>
-                        <div className="text-green-400 bg-green-900/10">+{'            let db_state = db::init(app.handle())?;'}</div>
-                        <div className="text-green-400 bg-green-900/10">+{'            app.manage(db_state);'}</div>
-                        <div className="text-green-400 bg-green-900/10">+</div>
-                        <div className="text-green-400 bg-green-900/10">+{'            // Spawn MCP SSE Server'}</div>
-                        <div className="text-green-400 bg-green-900/10">+{'                mcp::sse::start_sse_server().await;'}</div>
-                        <div className="text-green-400 bg-green-900/10">+{'            });'}</div>
-                        <div className="text-gray-400">{'             Ok(())'}</div>
-                        <div className="text-gray-400">{'         })'}</div>
+                        {request && typeof request.arguments.content === "string" ? (
+                            request.arguments.content.split("\n").map((line, i) => (
+                                <div key={i} className="text-green-400 bg-green-900/10 whitespace-pre-wrap">+{line}</div>
+                            ))
+                        ) : (
+                            <pre className="text-gray-400 whitespace-pre-wrap">{request ? JSON.stringify(request.arguments, null, 2) : ""}</pre>
+                        )}
                     </div>
                 </div>
 
                 {/* Footer Actions */}
                 <div className="h-20 border-t border-border-dark bg-background-dark flex items-center justify-end px-6 gap-4">
                     <div className="mr-auto text-xs font-mono text-gray-500">
-                        {t.status.pending} • {t.status.usage}: 1240 TOKENS • {t.status.risk}: {t.risks.medium}
+                        {t.status.pending} • {t.status.queue}: {pendingCount}
+                        {request && <> • {t.status.expires}: {new Date(request.expires_at).toLocaleTimeString()}</>}
+                        {request && <> • {t.status.risk}: {t.risks[riskOf(request.tool)]}</>}
                     </div>
                     
                     <button onClick={onReject} className="flex items-center gap-2 px-6 py-3 rounded border border-red-500/30 text-red-500 hover:bg-red-500/10 transition-colors font-bold tracking-wider">
@@ -10,6 +10,9 @@ export interface AgentRole {
     type: 'ai' | 'human';
     isDefault?: boolean;
     systemPrompt?: string; // AI 指令 - 用於 MCP 傳送給 AI IDE
+    allowedTools?: string[]; // 可使用的 MCP Tools，未設定時使用內建權限
+    allowedResources?: string[]; // 可讀取的 MCP Resources (結尾 * 為前綴比對)
+    baseRole?: 'coder' | 'reviewer' | 'architect'; // 自訂角色被選用時扮演的工作流程階段，預設 coder
 }
 
 interface RoleSettingsPageProps {
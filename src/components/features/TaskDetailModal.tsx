@@ -18,6 +18,7 @@ export interface TaskData {
     phase: string;
     priority: string;
     isReworked?: boolean;
+    readyForReview?: boolean; // 開發者已標記可交付審查
     assignee?: string;
 }
 
@@ -1,4 +1,5 @@
 import { useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
 import { zh_TW, TranslationType } from '../locales/zh-TW';
 import { zh_CN } from '../locales/zh-CN';
 import { en_US } from '../locales/en-US';
@@ -25,6 +26,8 @@ const languageEventTarget = new EventTarget();
 
 export function setLanguage(lang: LanguageCode) {
     localStorage.setItem('taskrails_lang', lang);
+    // The backend localizes the Hello Protocol banner with it
+    invoke('set_setting', { key: 'language', value: lang }).catch(console.error);
     languageEventTarget.dispatchEvent(new CustomEvent('lang_change', { detail: lang }));
 }
 
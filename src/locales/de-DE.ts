@@ -56,6 +56,7 @@ export const de_DE: TranslationType = {
         welcome: "Hallo! Ich bin Ihr technischer Architekt. Sagen Sie mir, was Sie entwickeln möchten, und ich helfe Ihnen bei der Spezifikation.",
         copy: "Kopieren",
         stop: "Stopp",
+        stopped: "(Generierung gestoppt)",
         systemPrompt: "System-Prompt",
         savePrompt: "Prompt speichern",
         copySuccess: "In Zwischenablage kopiert",
@@ -233,6 +234,8 @@ export const de_DE: TranslationType = {
     status: {
         pending: "Wartet auf Freigabe",
         usage: "TOKEN-VERBRAUCH",
+        queue: "WARTESCHLANGE",
+        expires: "LÄUFT AB",
         risk: "RISIKO"
     },
     risks: {
@@ -56,6 +56,7 @@ export const en_US: TranslationType = {
         welcome: "Hello! I am your technical architect. Please tell me what you want to develop, and I will assist you in perfecting the specification document through questions.",
         copy: "Copy",
         stop: "Stop",
+        stopped: "(generation stopped)",
         systemPrompt: "System Prompt",
         savePrompt: "Save Prompt",
         copySuccess: "Copied to clipboard",
@@ -233,6 +234,8 @@ export const en_US: TranslationType = {
     status: {
         pending: "Awaiting Approval",
         usage: "TOKEN USAGE",
+        queue: "QUEUE",
+        expires: "EXPIRES",
         risk: "RISK"
     },
     risks: {
@@ -56,6 +56,7 @@ export const es_ES: TranslationType = {
         welcome: "¡Hola! Soy tu arquitecto técnico. Cuéntame qué quieres desarrollar y te ayudaré a perfeccionar la especificación mediante preguntas.",
         copy: "Copiar",
         stop: "Detener",
+        stopped: "(generación detenida)",
         systemPrompt: "Prompt del Sistema",
         savePrompt: "Guardar Prompt",
         copySuccess: "Copiado al portapapeles",
@@ -233,6 +234,8 @@ export const es_ES: TranslationType = {
     status: {
         pending: "Esperando Aprobación",
         usage: "USO DE TOKENS",
+        queue: "COLA",
+        expires: "EXPIRA",
         risk: "RIESGO"
     },
     risks: {
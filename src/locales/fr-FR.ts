@@ -56,6 +56,7 @@ export const fr_FR: TranslationType = {
         welcome: "Bonjour ! Je suis votre architecte technique. Dites-moi ce que vous souhaitez développer, et je vous aiderai à affiner les spécifications via des questions.",
         copy: "Copier",
         stop: "Arrêter",
+        stopped: "(génération arrêtée)",
         systemPrompt: "Prompt Système",
         savePrompt: "Sauvegarder le Prompt",
         copySuccess: "Copié dans le presse-papiers",
@@ -233,6 +234,8 @@ export const fr_FR: TranslationType = {
     status: {
         pending: "En attente",
         usage: "USAGE TOKEN",
+        queue: "FILE D'ATTENTE",
+        expires: "EXPIRE",
         risk: "RISQUE"
     },
     risks: {
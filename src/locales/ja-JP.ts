@@ -56,6 +56,7 @@ export const ja_JP: TranslationType = {
         welcome: "こんにちは！私はあなたのテクニカルアーキテクトです。何を開発したいか教えていただければ、質問を通じて仕様書の完成をお手伝いします。",
         copy: "コピー",
         stop: "停止",
+        stopped: "（生成を停止しました）",
         systemPrompt: "システムプロンプト",
         savePrompt: "プロンプトを保存",
         copySuccess: "クリップボードにコピーしました",
@@ -233,6 +234,8 @@ export const ja_JP: TranslationType = {
     status: {
         pending: "承認待ち",
         usage: "トークン使用量",
+        queue: "キュー",
+        expires: "期限",
         risk: "リスク"
     },
     risks: {
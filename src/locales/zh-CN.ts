@@ -56,6 +56,7 @@ export const zh_CN: TranslationType = {
         welcome: "您好！我是您的技术架构师。请告诉我您想开发什么，我会通过提問协助您完善规格说明书。",
         copy: "复制",
         stop: "停止生成",
+        stopped: "（已停止生成）",
         systemPrompt: "系统指令 (System Prompt)",
         savePrompt: "更新指令",
         copySuccess: "已复制到剪贴板",
@@ -233,6 +234,8 @@ export const zh_CN: TranslationType = {
     status: {
         pending: "等待批准",
         usage: "TOKEN 使用量",
+        queue: "队列",
+        expires: "到期",
         risk: "风险"
     },
     risks: {
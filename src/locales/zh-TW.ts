@@ -56,6 +56,7 @@ export const zh_TW = {
         welcome: "您好！我是您的技術架構師。請告訴我您想開發什麼，我會透過提問協助您完善規格說明書。",
         copy: "複製",
         stop: "停止生成",
+        stopped: "（已停止生成）",
         systemPrompt: "系統指令 (System Prompt)",
         savePrompt: "更新指令",
         copySuccess: "已複製到剪貼簿",
@@ -233,6 +234,8 @@ export const zh_TW = {
     status: {
         pending: "等待批准",
         usage: "TOKEN 使用量",
+        queue: "佇列",
+        expires: "到期",
         risk: "風險"
     },
     risks: {